@@ -0,0 +1,274 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Error, Result};
+use glob::{glob, Pattern};
+use thiserror::Error;
+
+use crate::{
+    lexer::{Delimiters, LexerError, Syntax}, load_paths, loader::Loader, naming::Naming, node::Escaper, LoadOptions,
+    sandbox::DynamicNamePolicy, Rustache, DEFAULT_MAX_DEPTH,
+};
+
+#[derive(Error, Debug)]
+pub enum BuilderError {
+    #[error("no glob patterns or extensions given for the templates in: '{0}'")]
+    NoTemplatePatterns(String),
+    #[error("invalid delimiters: {0}")]
+    InvalidDelimiters(#[from] LexerError),
+}
+
+/// The threads templates are lexed and parsed on while loading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Parallelism {
+    /// A dedicated pool with one thread per available core.
+    #[default]
+    Available,
+    /// A dedicated pool with a fixed number of threads.
+    Threads(usize),
+    /// The global rayon pool, shared with the rest of the application.
+    Global,
+}
+
+impl Parallelism {
+    /// The size of the dedicated pool to build, or `None` for the global pool.
+    pub(crate) fn threads(&self) -> Option<usize> {
+        return match self {
+            Parallelism::Available => Some(
+                std::thread::available_parallelism()
+                    .map(|threads| threads.get())
+                    .unwrap_or(1),
+            ),
+            Parallelism::Threads(threads) => Some(*threads),
+            Parallelism::Global => None,
+        };
+    }
+}
+
+/// Configures how a [`Rustache`] finds, parses and renders the templates in a directory.
+/// Every template matching one of the glob patterns is loaded, unless it matches an exclude
+/// pattern or the builder is restricted to a set of file extensions. At least one glob pattern
+/// or extension is required, so unrelated files in the directory are never parsed.
+#[derive(Debug, Clone)]
+pub struct RustacheBuilder {
+    pub directory: String,
+    pub globs: Vec<String>,
+    pub excludes: Vec<String>,
+    pub extensions: Vec<String>,
+    pub parallelism: Parallelism,
+    pub syntax: Syntax,
+    pub escaper: Escaper,
+    pub strict: bool,
+    pub length_property: bool,
+    pub naming: Naming,
+    pub max_depth: usize,
+    pub dynamic_names: DynamicNamePolicy,
+}
+
+impl RustacheBuilder {
+    pub fn new(directory: &str) -> Self {
+        return Self {
+            directory: directory.into(),
+            globs: Vec::new(),
+            excludes: Vec::new(),
+            extensions: Vec::new(),
+            parallelism: Parallelism::default(),
+            syntax: Syntax::default(),
+            escaper: Escaper::default(),
+            strict: true,
+            length_property: false,
+            naming: Naming::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            dynamic_names: DynamicNamePolicy::default(),
+        };
+    }
+
+    /// Adds a glob pattern relative to the directory. Files with one of the extensions are
+    /// loaded from anywhere beneath the directory when no patterns are given.
+    pub fn glob(mut self, pattern: impl Into<String>) -> Self {
+        self.globs.push(pattern.into());
+        return self;
+    }
+
+    /// Skips files whose path relative to the directory matches the glob pattern.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.excludes.push(pattern.into());
+        return self;
+    }
+
+    /// Only loads files with the extension, given without its leading dot.
+    pub fn extension(mut self, extension: impl Into<String>) -> Self {
+        self.extensions.push(extension.into());
+        return self;
+    }
+
+    /// Parses templates on a dedicated pool with a fixed number of threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.parallelism = Parallelism::Threads(threads);
+        return self;
+    }
+
+    /// Parses templates on the global rayon pool rather than building a dedicated one.
+    pub fn global_pool(mut self) -> Self {
+        self.parallelism = Parallelism::Global;
+        return self;
+    }
+
+    /// The delimiters templates start with, `{{` and `}}` by default. Like Set Delimiter tags
+    /// they cannot be empty or contain whitespace or `=`, which `build` reports.
+    pub fn delimiters(mut self, open: impl Into<String>, close: impl Into<String>) -> Self {
+        self.syntax.delimiters = Delimiters::new(open, close);
+        return self;
+    }
+
+    /// Whether partial tags take named arguments, `{{> card title=post.title}}`, which the
+    /// spec does not define so it is off by default.
+    pub fn partial_arguments(mut self, partial_arguments: bool) -> Self {
+        self.syntax.partial_arguments = partial_arguments;
+        return self;
+    }
+
+    /// Whether a backslash before an open delimiter, `\{{name}}`, keeps the delimiter as text.
+    /// Off by default so backslashes in text, such as `C:\{{dir}}`, render as written.
+    pub fn escaped_delimiters(mut self, escaped_delimiters: bool) -> Self {
+        self.syntax.escaped_delimiters = escaped_delimiters;
+        return self;
+    }
+
+    pub fn escaper(mut self, escaper: Escaper) -> Self {
+        self.escaper = escaper;
+        return self;
+    }
+
+    /// Whether missing identifiers are errors, when disabled they render as empty.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        return self;
+    }
+
+    /// Whether `items.length` resolves to the number of items in a list, which the spec does
+    /// not define so it is off by default.
+    pub fn length_property(mut self, length_property: bool) -> Self {
+        self.length_property = length_property;
+        return self;
+    }
+
+    pub fn naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        return self;
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        return self;
+    }
+
+    pub fn dynamic_names(mut self, dynamic_names: DynamicNamePolicy) -> Self {
+        self.dynamic_names = dynamic_names;
+        return self;
+    }
+
+    /// Loads and parses every matching template.
+    pub fn build(self) -> Result<Rustache, Error> {
+        if let Err(error) = self.syntax.delimiters.validate() {
+            return Err(BuilderError::from(error).into());
+        }
+        let options = LoadOptions {
+            naming: &self.naming,
+            parallelism: &self.parallelism,
+            syntax: &self.syntax,
+        };
+        let templates = load_paths(&self.directory, self.paths()?, &options)?;
+
+        let rustache = Rustache::from_templates(&self.directory, templates)?;
+
+        return Ok(self.configure(rustache));
+    }
+
+    /// Parses every template provided by a loader up front, the glob, exclude and extension
+    /// filters only apply to directories.
+    pub fn build_from_loader(self, loader: impl Loader + 'static) -> Result<Rustache, Error> {
+        let rustache = self.build_lazy(loader)?;
+
+        for template in rustache.partials.values() {
+            template.nodes()?;
+        }
+
+        return Ok(rustache);
+    }
+
+    /// Resolves templates through a loader, parsing each one the first time it is rendered.
+    pub fn build_lazy(self, loader: impl Loader + 'static) -> Result<Rustache, Error> {
+        if let Err(error) = self.syntax.delimiters.validate() {
+            return Err(BuilderError::from(error).into());
+        }
+        return Ok(self.lazy(loader));
+    }
+
+    pub(crate) fn lazy(self, loader: impl Loader + 'static) -> Rustache {
+        let rustache = Rustache::lazy_with_syntax(loader, &self.syntax);
+        return self.configure(rustache);
+    }
+
+    fn configure(self, mut rustache: Rustache) -> Rustache {
+        rustache.directory = self.directory;
+        rustache.max_depth = self.max_depth;
+        rustache.dynamic_names = self.dynamic_names;
+        rustache.escaper = self.escaper;
+        rustache.strict = self.strict;
+        rustache.length_property = self.length_property;
+        rustache.syntax = self.syntax;
+        return rustache;
+    }
+
+    fn paths(&self) -> Result<Vec<PathBuf>, Error> {
+        let excludes = self
+            .excludes
+            .iter()
+            .map(|pattern| Pattern::new(pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let globs = match (self.globs.is_empty(), self.extensions.is_empty()) {
+            (true, true) => return Err(BuilderError::NoTemplatePatterns(self.directory.clone()).into()),
+            (true, false) => vec![String::from("**/*")],
+            (false, _) => self.globs.clone(),
+        };
+
+        let mut paths = Vec::new();
+
+        for pattern in &globs {
+            for path in glob(&[self.directory.as_str(), "/", pattern].concat())? {
+                let Ok(path) = path else {
+                    continue;
+                };
+                if path.is_file() && self.includes(&path, &excludes) && !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+
+        return Ok(paths);
+    }
+
+    fn includes(&self, path: &Path, excludes: &[Pattern]) -> bool {
+        if !self.extensions.is_empty() {
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            if !self
+                .extensions
+                .iter()
+                .any(|other| Some(other.as_str()) == extension)
+            {
+                return false;
+            }
+        }
+
+        // Excludes match the path beneath the directory including its extension
+        let relative = Naming::new()
+            .keep_extensions(true)
+            .name(&self.directory, path);
+
+        return match relative {
+            Some(relative) => !excludes.iter().any(|pattern| pattern.matches(&relative)),
+            None => true,
+        };
+    }
+}
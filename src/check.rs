@@ -0,0 +1,175 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    path::PathBuf,
+};
+
+use anyhow::{Error, Result};
+
+use crate::{
+    dependencies::Dependencies,
+    lexer::Syntax,
+    load,
+    naming::{resolve_relative, Naming},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        };
+    }
+}
+
+/// A single problem found in a template file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: PathBuf,
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Syntax errors already lead with their line and column
+        return match self.position {
+            Some(_) => write!(
+                f,
+                "{}: {}:{}",
+                self.severity,
+                self.path.display(),
+                self.message
+            ),
+            None => write!(
+                f,
+                "{}: {}: {}",
+                self.severity,
+                self.path.display(),
+                self.message
+            ),
+        };
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        return self
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error);
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        return self
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Warning);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        return self.errors().next().is_some();
+    }
+}
+
+/// Parses every template matched by `glob_pattern` inside `directory` and reports syntax errors,
+/// static partial and parent references that do not resolve, and templates that are never
+/// referenced by another template.
+pub fn check(directory: &str, glob_pattern: &str) -> Result<Report, Error> {
+    return check_with_syntax(directory, glob_pattern, &Syntax::default());
+}
+
+/// Checks templates written with custom starting delimiters or opt-in syntax, which must match
+/// the syntax the templates are rendered with.
+pub fn check_with_syntax(directory: &str, glob_pattern: &str, syntax: &Syntax) -> Result<Report, Error> {
+    if let Err(error) = syntax.delimiters.validate() {
+        return Err(error.into());
+    }
+
+    let mut report = Report::default();
+    let mut templates = HashMap::new();
+
+    for template in load(directory, glob_pattern, &Naming::default(), syntax)? {
+        match template.nodes {
+            Ok(nodes) => {
+                templates.insert(template.name, (template.path, nodes));
+            }
+            Err(error) => report.diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                path: template.path,
+                position: error.position(),
+                message: error.to_string(),
+            }),
+        }
+    }
+
+    let mut referenced = HashSet::new();
+
+    for (name, (path, nodes)) in &templates {
+        let dependencies = Dependencies::of(nodes);
+        let references = dependencies
+            .partials
+            .iter()
+            .filter(|reference| !dependencies.definitions.contains(&reference.name))
+            .map(|reference| ("partial", reference))
+            .chain(
+                dependencies
+                    .parents
+                    .iter()
+                    .map(|reference| ("parent", reference)),
+            )
+            .filter(|(_, reference)| !reference.dynamic);
+
+        for (kind, reference) in references {
+            let Some(reference_name) = resolve_relative(name, &reference.name) else {
+                report.diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    path: path.clone(),
+                    position: None,
+                    message: format!("{}: '{}' escapes the views directory", kind, reference.name),
+                });
+                continue;
+            };
+            if &reference_name != name {
+                referenced.insert(reference_name.clone());
+            }
+            if !templates.contains_key(&reference_name) {
+                report.diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    path: path.clone(),
+                    position: None,
+                    message: format!("{}: '{}' does not exist", kind, reference.name),
+                });
+            }
+        }
+    }
+
+    for (name, (path, _)) in &templates {
+        if !referenced.contains(name) {
+            report.diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                path: path.clone(),
+                position: None,
+                message: format!("template: '{}' is never referenced", name),
+            });
+        }
+    }
+
+    report
+        .diagnostics
+        .sort_by(|a, b| (&a.path, a.position).cmp(&(&b.path, b.position)));
+
+    return Ok(report);
+}
@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::Serialize;
+
+use crate::{
+    loader::Template,
+    naming::resolve_relative,
+    node::{Argument, Node},
+};
+
+/// A name referenced by a template. Dynamic references name the context identifier the
+/// template or key is looked up from rather than the template or key itself.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Reference {
+    pub name: String,
+    pub dynamic: bool,
+}
+
+impl Reference {
+    fn new(name: &str, dynamic: bool) -> Self {
+        return Self {
+            name: name.into(),
+            dynamic,
+        };
+    }
+}
+
+/// Everything a single template references, found by walking its parsed nodes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Dependencies {
+    pub partials: BTreeSet<Reference>,
+    pub parents: BTreeSet<Reference>,
+    pub blocks: BTreeSet<String>,
+    /// Partials defined inline with `{{*define name}}`.
+    pub definitions: BTreeSet<String>,
+    pub variables: BTreeSet<Reference>,
+    pub sections: BTreeSet<Reference>,
+}
+
+impl Dependencies {
+    pub fn of(nodes: &[Node]) -> Self {
+        let mut dependencies = Self::default();
+        dependencies.walk(nodes);
+        return dependencies;
+    }
+
+    /// Resolves relative references against the name of the template they were found in.
+    /// References that escape above the root are kept as written.
+    pub fn relative_to(mut self, name: &str) -> Self {
+        for references in [&mut self.partials, &mut self.parents] {
+            *references = std::mem::take(references)
+                .into_iter()
+                .map(|reference| match reference.dynamic {
+                    true => reference,
+                    false => Reference {
+                        name: resolve_relative(name, &reference.name).unwrap_or(reference.name),
+                        dynamic: false,
+                    },
+                })
+                .collect();
+        }
+        return self;
+    }
+
+    /// Templates referenced by name, dynamic references cannot be resolved statically and
+    /// partials defined inline are not templates of their own.
+    pub fn templates(&self) -> impl Iterator<Item = &str> {
+        return self
+            .partials
+            .iter()
+            .filter(|reference| !self.definitions.contains(&reference.name))
+            .chain(self.parents.iter())
+            .filter(|reference| !reference.dynamic)
+            .map(|reference| reference.name.as_str());
+    }
+
+    fn walk(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            match node {
+                Node::Variable {
+                    identifier,
+                    dynamic,
+                    ..
+                } => {
+                    self.variables.insert(Reference::new(identifier, *dynamic));
+                }
+                Node::Section {
+                    identifier,
+                    dynamic,
+                    children,
+                    ..
+                } => {
+                    self.sections.insert(Reference::new(identifier, *dynamic));
+                    self.walk(children);
+                }
+                Node::Partial {
+                    identifier,
+                    dynamic,
+                    arguments,
+                } => {
+                    if *dynamic {
+                        self.variables.insert(Reference::new(identifier, false));
+                    }
+                    for (_, argument) in arguments {
+                        if let Argument::Identifier(identifier) = argument {
+                            self.variables.insert(Reference::new(identifier, false));
+                        }
+                    }
+                    self.partials.insert(Reference::new(identifier, *dynamic));
+                }
+                Node::Parent {
+                    identifier,
+                    dynamic,
+                    children,
+                } => {
+                    if *dynamic {
+                        self.variables.insert(Reference::new(identifier, false));
+                    }
+                    self.parents.insert(Reference::new(identifier, *dynamic));
+                    self.walk(children);
+                }
+                Node::Block {
+                    identifier,
+                    children,
+                } => {
+                    self.blocks.insert(identifier.clone());
+                    self.walk(children);
+                }
+                Node::Definition {
+                    identifier,
+                    children,
+                } => {
+                    self.definitions.insert(identifier.clone());
+                    self.walk(children);
+                }
+                Node::Text(_)
+                | Node::Implicit
+                | Node::Comment(_)
+                | Node::Super
+                | Node::Pragma(_) => {}
+            }
+        }
+    }
+}
+
+/// The dependencies of every template in a registry.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DependencyGraph {
+    pub templates: BTreeMap<String, Dependencies>,
+}
+
+impl DependencyGraph {
+    /// Templates that fail to load are left out of the graph.
+    pub fn new(partials: &HashMap<String, Template>) -> Self {
+        return Self {
+            templates: partials
+                .iter()
+                .filter_map(|(name, template)| {
+                    let nodes = template.nodes().ok()?;
+                    Some((name.clone(), Dependencies::of(nodes).relative_to(name)))
+                })
+                .collect(),
+        };
+    }
+
+    /// Templates that include `name`, directly or through other templates.
+    pub fn dependents(&self, name: &str) -> BTreeSet<&str> {
+        let mut dependents = BTreeSet::new();
+        let mut pending = vec![name];
+
+        while let Some(current) = pending.pop() {
+            for (template, dependencies) in &self.templates {
+                if dependencies.templates().any(|other| other == current)
+                    && dependents.insert(template.as_str())
+                {
+                    pending.push(template.as_str());
+                }
+            }
+        }
+
+        return dependents;
+    }
+
+    /// Include cycles between templates, each listed from the first template in the cycle
+    /// and ending with the template that includes it again.
+    pub fn cycles(&self) -> Vec<Vec<String>> {
+        let mut cycles = Vec::new();
+        let mut visited = BTreeSet::new();
+
+        for name in self.templates.keys() {
+            let mut path = Vec::new();
+            self.find_cycles(name, &mut path, &mut visited, &mut cycles);
+        }
+
+        return cycles;
+    }
+
+    fn find_cycles<'a>(
+        &'a self,
+        name: &'a str,
+        path: &mut Vec<&'a str>,
+        visited: &mut BTreeSet<&'a str>,
+        cycles: &mut Vec<Vec<String>>,
+    ) {
+        if let Some(start) = path.iter().position(|other| *other == name) {
+            let mut cycle = path[start..]
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>();
+            cycle.push(name.into());
+            cycles.push(cycle);
+            return;
+        }
+
+        if !visited.insert(name) {
+            return;
+        }
+
+        if let Some(dependencies) = self.templates.get(name) {
+            path.push(name);
+            for other in dependencies.templates() {
+                self.find_cycles(other, path, visited, cycles);
+            }
+            path.pop();
+        }
+    }
+
+    /// Renders the partial and parent edges in Graphviz DOT format. Dynamic references point
+    /// at a dashed node named after the identifier they are resolved from.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph templates {\n");
+
+        for (name, dependencies) in &self.templates {
+            dot.push_str(&format!("    {:?};\n", name));
+
+            for (kind, references) in [
+                ("partial", &dependencies.partials),
+                ("parent", &dependencies.parents),
+            ] {
+                for reference in references {
+                    if reference.dynamic {
+                        dot.push_str(&format!(
+                            "    {:?} -> {:?} [label={:?}, style=dashed];\n",
+                            name,
+                            ["*", &reference.name].concat(),
+                            kind
+                        ));
+                    } else {
+                        dot.push_str(&format!(
+                            "    {:?} -> {:?} [label={:?}];\n",
+                            name, reference.name, kind
+                        ));
+                    }
+                }
+            }
+        }
+
+        dot.push_str("}\n");
+        return dot;
+    }
+
+    pub fn to_json(&self) -> String {
+        return serde_json::to_string_pretty(self).expect("failed to serialize dependency graph");
+    }
+}
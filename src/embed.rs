@@ -0,0 +1,75 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::{Error, Result};
+use glob::glob;
+
+use crate::naming::Naming;
+
+/// Generates Rust source embedding every template matched by `glob_pattern` inside
+/// `directory`, for use from a build script. Templates are named exactly as
+/// [`crate::Rustache::new`] would name them, and the file evaluates to a
+/// `&'static [(&'static str, &'static str)]` of names and sources that can be passed to
+/// [`crate::Rustache::embedded`] through [`crate::include_views`].
+///
+/// ```no_run
+/// // build.rs
+/// let out_dir = std::env::var("OUT_DIR").unwrap();
+/// rustache::embed::generate("views", "**/*.mustache", format!("{}/views.rs", out_dir))
+///     .expect("failed to embed views");
+/// ```
+pub fn generate(
+    directory: &str,
+    glob_pattern: &str,
+    out_file: impl AsRef<Path>,
+) -> Result<(), Error> {
+    return generate_with_naming(directory, glob_pattern, &Naming::default(), out_file);
+}
+
+/// Generates embedded templates named by a custom [`Naming`] strategy.
+pub fn generate_with_naming(
+    directory: &str,
+    glob_pattern: &str,
+    naming: &Naming,
+    out_file: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let mut source = String::from("&[\n");
+
+    let paths = glob(&[directory, "/", glob_pattern].concat())?.filter_map(|entry| entry.ok());
+    let mut templates = naming
+        .names(directory, paths)?
+        .into_iter()
+        .collect::<Vec<_>>();
+    templates.sort();
+
+    for (name, path) in templates {
+        let absolute_path = path.canonicalize()?;
+
+        writeln!(
+            source,
+            "    ({:?}, include_str!({:?})),",
+            name,
+            absolute_path.display().to_string()
+        )?;
+    }
+
+    source.push(']');
+
+    std::fs::write(out_file, source)?;
+
+    // Rebuild whenever a template is added, removed or changed
+    println!("cargo:rerun-if-changed={}", directory);
+
+    return Ok(());
+}
+
+/// Includes templates embedded by [`embed::generate`](crate::embed::generate) from `OUT_DIR`.
+///
+/// ```ignore
+/// let rustache = Rustache::embedded(rustache::include_views!("views.rs"))?;
+/// ```
+#[macro_export]
+macro_rules! include_views {
+    ($file:expr) => {
+        include!(concat!(env!("OUT_DIR"), "/", $file))
+    };
+}
@@ -3,11 +3,6 @@ use std::io::{BufRead, Read};
 use substring::Substring;
 use thiserror::Error;
 
-const VALID_IDENTIFIER_CHARACTER_SET: &str =
-    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.?_/";
-
-const VALID_DELIMITER_CHARACTER_SET: &str = "<>%()@{}";
-
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Error {
@@ -17,7 +12,10 @@ pub enum Token {
     },
     EOF,
     Text(String),
-    OpenDelimiter,
+    OpenDelimiter {
+        line: usize,
+        column: usize,
+    },
     CloseDelimiter,
     Section,
     InvertedSection,
@@ -30,19 +28,39 @@ pub enum Token {
     Parent,
     SetDelimiter,
     Raw,
+    Special,
+    Argument(String),
+    Literal(String),
+    Define,
+    Pragma,
+    Verbatim(String),
     SectionEnd,
 }
 
 #[derive(Error, Debug, Clone)]
 pub enum LexerError {
-    #[error("unclosed delimiter")]
-    UnclosedDelimiter,
     #[error("unexpected end of file")]
     UnexpectedEOF,
     #[error("unexpected character: '{0}'")]
     UnexpectedCharacter(char),
+    #[error("expected identifier got: '{0}'")]
+    ExpectedIdentifier(char),
     #[error("expected character: '{0}' got: '{1}'")]
     ExpectedCharacter(char, char),
+    #[error("unclosed string")]
+    UnclosedString,
+    #[error("delimiters cannot be empty")]
+    EmptyDelimiter,
+    #[error("delimiter: '{0}' cannot contain whitespace or '='")]
+    InvalidDelimiter(String),
+    #[error("unclosed verbatim block")]
+    UnclosedVerbatim,
+    #[error("unknown directive: '{0}'")]
+    UnknownDirective(String),
+    #[error("invalid UTF-8")]
+    InvalidUtf8,
+    #[error("failed to read template: {0}")]
+    Read(String),
 }
 
 pub struct Lexer<R: Read + BufRead> {
@@ -57,6 +75,8 @@ pub struct Lexer<R: Read + BufRead> {
     close_delimiter_chars: usize,
     raw_close_delimiter: String,
     raw_close_delimiter_chars: usize,
+    partial_arguments: bool,
+    escaped_delimiters: bool,
 }
 
 trait State<R: Read + BufRead> {
@@ -65,8 +85,64 @@ trait State<R: Read + BufRead> {
 
 type StateFunction<R> = Option<Box<dyn State<R>>>;
 
+/// The delimiters a template starts with, before any Set Delimiter tags.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delimiters {
+    pub open: String,
+    pub close: String,
+}
+
+impl Delimiters {
+    pub fn new(open: impl Into<String>, close: impl Into<String>) -> Self {
+        return Self {
+            open: open.into(),
+            close: close.into(),
+        };
+    }
+
+    /// Checks the delimiters follow the same rules as Set Delimiter tags.
+    pub fn validate(&self) -> Result<(), LexerError> {
+        for delimiter in [&self.open, &self.close] {
+            if delimiter.is_empty() {
+                return Err(LexerError::EmptyDelimiter);
+            }
+            if !delimiter.chars().all(is_delimiter_character) {
+                return Err(LexerError::InvalidDelimiter(delimiter.clone()));
+            }
+        }
+        return Ok(());
+    }
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        return Self::new("{{", "}}");
+    }
+}
+
+/// The syntax templates are lexed with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Syntax {
+    pub delimiters: Delimiters,
+    /// Whether partial tags take named arguments, `{{> card title=post.title}}`.
+    pub partial_arguments: bool,
+    /// Whether `\{{` is an open delimiter kept as text, and `\\{{` a backslash before a tag.
+    pub escaped_delimiters: bool,
+}
+
 pub fn lex<R: Read + BufRead>(reader: R, sender: Sender<Token>) {
+    lex_with_syntax(reader, sender, &Syntax::default());
+}
+
+pub fn lex_with_syntax<R: Read + BufRead>(reader: R, sender: Sender<Token>, syntax: &Syntax) {
     let mut lexer = Lexer::new(reader, sender);
+    if let Err(error) = lexer.read().and_then(|_| syntax.delimiters.validate()) {
+        lexer.emit_error(error);
+        return;
+    }
+    lexer.set_delimiters(syntax.delimiters.open.clone(), syntax.delimiters.close.clone());
+    lexer.partial_arguments = syntax.partial_arguments;
+    lexer.escaped_delimiters = syntax.escaped_delimiters;
     let mut state_function: StateFunction<R> = Some(Box::new(LexText));
     while let Some(mut state) = state_function {
         state_function = state.next(&mut lexer);
@@ -84,6 +160,28 @@ impl<R: Read + BufRead> State<R> for LexText {
                 }
                 return Some(Box::new(LexOpenDelimiter));
             }
+            if lexer.escaped_delimiters && lexer.peekn(1) == "\\" {
+                // A backslash before an open delimiter keeps the delimiter as text, and a second
+                // backslash keeps a single backslash before the tag
+                let escaped_tag = ["\\\\", &lexer.open_delimiter].concat();
+                let escaped_delimiter = ["\\", &lexer.open_delimiter].concat();
+                let escaped = if lexer.peekn(lexer.open_delimiter_chars + 2) == escaped_tag {
+                    1
+                } else if lexer.peekn(lexer.open_delimiter_chars + 1) == escaped_delimiter {
+                    lexer.open_delimiter_chars
+                } else {
+                    0
+                };
+                if escaped > 0 {
+                    if lexer.position > lexer.start_position {
+                        lexer.emit(Token::Text(lexer.current()));
+                    }
+                    lexer.next();
+                    lexer.ignore();
+                    lexer.nextn(escaped);
+                    continue;
+                }
+            }
             if lexer.next().is_none() {
                 break;
             }
@@ -100,8 +198,10 @@ struct LexOpenDelimiter;
 
 impl<R: Read + BufRead> State<R> for LexOpenDelimiter {
     fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
+        let line = lexer.line();
+        let column = lexer.column() + 1;
         lexer.nextn(lexer.open_delimiter_chars);
-        lexer.emit(Token::OpenDelimiter);
+        lexer.emit(Token::OpenDelimiter { line, column });
         return Some(Box::new(LexInsideDelimiter));
     }
 }
@@ -117,8 +217,16 @@ impl<R: Read + BufRead> State<R> for LexInsideDelimiter {
                     return Some(Box::new(LexIdentifier));
                 }
                 '.' => {
-                    lexer.emit(Token::Implicit);
-                    return Some(Box::new(LexCloseDelimiter));
+                    // `{{.name}}` is an anchored name rather than the implicit iterator
+                    let next = lexer.peekn(1);
+                    if next.trim().is_empty()
+                        || lexer.peekn(lexer.close_delimiter_chars) == lexer.close_delimiter
+                    {
+                        lexer.emit(Token::Implicit);
+                        return Some(Box::new(LexCloseDelimiter));
+                    }
+                    lexer.backup(1);
+                    return Some(Box::new(LexIdentifier));
                 }
                 '!' => {
                     return Some(Box::new(LexComment));
@@ -149,22 +257,35 @@ impl<R: Read + BufRead> State<R> for LexInsideDelimiter {
                 }
                 '>' => {
                     lexer.emit(Token::Partial);
+                    return Some(Box::new(LexPartialIdentifier {
+                        arguments: lexer.partial_arguments,
+                    }));
+                }
+                '*' => {
+                    lexer.ignore();
+                    return Some(Box::new(LexDirective));
+                }
+                '@' => {
+                    lexer.emit(Token::Special);
                     return Some(Box::new(LexIdentifier));
                 }
+                '%' => {
+                    // Pragmas take `name=value` arguments like partials
+                    lexer.emit(Token::Pragma);
+                    return Some(Box::new(LexPartialIdentifier { arguments: true }));
+                }
                 '=' => {
                     lexer.emit(Token::SetDelimiter);
                     return Some(Box::new(LexNewDelimiter));
                 }
-                ' ' => {
+                next_character if next_character.is_whitespace() => {
                     lexer.ignore();
                     return Some(Box::new(LexIdentifier));
                 }
-                '\n' => return lexer.emit_error(LexerError::UnclosedDelimiter),
-                next_character if next_character.is_alphanumeric() => {
+                _ => {
                     lexer.backup(1);
                     return Some(Box::new(LexIdentifier));
                 }
-                _ => lexer.emit_error(LexerError::UnexpectedCharacter(next_character)),
             },
             None => lexer.emit_error(LexerError::UnexpectedEOF),
         };
@@ -176,7 +297,7 @@ struct LexCloseDelimiter;
 impl<R: Read + BufRead> State<R> for LexCloseDelimiter {
     fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
         // Ignore whitespace
-        lexer.accept_run(" ");
+        lexer.accept_while(char::is_whitespace);
         lexer.ignore();
 
         // Peek to see if we have reached a closing delimiter
@@ -200,33 +321,211 @@ struct LexIdentifier;
 impl<R: Read + BufRead> State<R> for LexIdentifier {
     fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
         // Ignore whitespace
-        lexer.accept_run(" ");
+        lexer.accept_while(char::is_whitespace);
         lexer.ignore();
 
         // Check if identifier is dynamic
-        if let (true, _) = lexer.accept("*") {
+        if lexer.peekn(1) == "*" {
+            lexer.next();
             lexer.emit(Token::Dynamic);
         }
 
-        // Consume valid characters and emit
-        lexer.accept_run(VALID_IDENTIFIER_CHARACTER_SET);
-        lexer.emit(Token::Identifier(lexer.current()));
+        if !lexer.emit_identifier("") {
+            return None;
+        }
 
         return Some(Box::new(LexCloseDelimiter));
     }
 }
 
+/// Lexes the keyword of a `{{*directive name}}` tag, any other name is a dynamic variable.
+struct LexDirective;
+
+impl<R: Read + BufRead> State<R> for LexDirective {
+    fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
+        // Ignore whitespace
+        lexer.accept_while(char::is_whitespace);
+        lexer.ignore();
+
+        lexer.accept_identifier("");
+
+        return match lexer.current().as_str() {
+            "define" => {
+                lexer.emit(Token::Define);
+                Some(Box::new(LexIdentifier))
+            }
+            "verbatim" => {
+                lexer.ignore();
+                Some(Box::new(LexVerbatim))
+            }
+            identifier => {
+                let identifier = identifier.to_string();
+                let characters = lexer.current().chars().count();
+                lexer.accept_while(char::is_whitespace);
+                let whitespace = lexer.current().chars().count() - characters;
+
+                // A second word makes it a directive, `{{*include row}}`, rather than a dynamic name
+                if whitespace > 0
+                    && !lexer.peekn(1).is_empty()
+                    && lexer.peekn(lexer.close_delimiter_chars) != lexer.close_delimiter
+                {
+                    return lexer.emit_error(LexerError::UnknownDirective(identifier));
+                }
+
+                lexer.backup(characters + whitespace);
+                lexer.emit(Token::Dynamic);
+                Some(Box::new(LexIdentifier))
+            }
+        };
+    }
+}
+
+/// Lexes the content of a `{{*verbatim}}` block as text, untouched up to `{{/verbatim}}`.
+struct LexVerbatim;
+
+impl<R: Read + BufRead> State<R> for LexVerbatim {
+    fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
+        // Ignore whitespace
+        lexer.accept_while(char::is_whitespace);
+        lexer.ignore();
+
+        if lexer.peekn(lexer.close_delimiter_chars) != lexer.close_delimiter {
+            return match lexer.next() {
+                None => lexer.emit_error(LexerError::UnexpectedEOF),
+                Some(character) => lexer.emit_error(LexerError::UnexpectedCharacter(character)),
+            };
+        }
+        lexer.nextn(lexer.close_delimiter_chars);
+        lexer.ignore();
+
+        let end = [
+            lexer.open_delimiter.as_str(),
+            "/verbatim",
+            lexer.close_delimiter.as_str(),
+        ]
+        .concat();
+        let end_chars = end.chars().count();
+
+        loop {
+            if lexer.peekn(end_chars) == end {
+                lexer.emit(Token::Verbatim(lexer.current()));
+                lexer.nextn(end_chars);
+                lexer.ignore();
+                return Some(Box::new(LexText));
+            }
+            if lexer.next().is_none() {
+                return lexer.emit_error(LexerError::UnclosedVerbatim);
+            }
+        }
+    }
+}
+
+struct LexPartialIdentifier {
+    /// Whether `name=value` arguments may follow the name.
+    arguments: bool,
+}
+
+impl<R: Read + BufRead> State<R> for LexPartialIdentifier {
+    fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
+        // Ignore whitespace
+        lexer.accept_while(char::is_whitespace);
+        lexer.ignore();
+
+        // Check if identifier is dynamic
+        if lexer.peekn(1) == "*" {
+            lexer.next();
+            lexer.emit(Token::Dynamic);
+        }
+
+        if !lexer.emit_identifier("=") {
+            return None;
+        }
+
+        if !self.arguments {
+            return Some(Box::new(LexCloseDelimiter));
+        }
+        return Some(Box::new(LexArguments));
+    }
+}
+
+/// Lexes the `name=value` arguments of a partial, where each value is an identifier or a
+/// double quoted string.
+struct LexArguments;
+
+impl<R: Read + BufRead> State<R> for LexArguments {
+    fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
+        // Ignore whitespace
+        lexer.accept_while(char::is_whitespace);
+        lexer.ignore();
+
+        if lexer.peekn(lexer.close_delimiter_chars) == lexer.close_delimiter {
+            return Some(Box::new(LexCloseDelimiter));
+        }
+
+        // Consume the argument name
+        lexer.accept_identifier("=\"");
+        let name = lexer.current();
+        if name.is_empty() {
+            return match lexer.next() {
+                None => lexer.emit_error(LexerError::UnexpectedEOF),
+                Some(character) => lexer.emit_error(LexerError::UnexpectedCharacter(character)),
+            };
+        }
+
+        match lexer.accept("=") {
+            (true, _) => lexer.emit(Token::Argument(name)),
+            (false, Some(got)) => return lexer.emit_error(LexerError::ExpectedCharacter('=', got)),
+            (false, None) => return lexer.emit_error(LexerError::UnexpectedEOF),
+        }
+
+        // Consume a quoted string or an identifier as the value
+        if lexer.peekn(1) == "\"" {
+            lexer.next();
+            lexer.ignore();
+            loop {
+                match lexer.next() {
+                    Some('"') => break,
+                    Some('\n') | None => return lexer.emit_error(LexerError::UnclosedString),
+                    Some(_) => {}
+                }
+            }
+            lexer.backup(1);
+            lexer.emit(Token::Literal(lexer.current()));
+            lexer.next();
+            lexer.ignore();
+        } else {
+            lexer.accept_identifier("=\"");
+            let value = lexer.current();
+            if value.is_empty() {
+                return match lexer.next() {
+                    None => lexer.emit_error(LexerError::UnexpectedEOF),
+                    Some(character) => lexer.emit_error(LexerError::UnexpectedCharacter(character)),
+                };
+            }
+            lexer.emit(Token::Identifier(value));
+        }
+
+        return Some(Box::new(LexArguments));
+    }
+}
+
 struct LexRawIdentifier;
 
 impl<R: Read + BufRead> State<R> for LexRawIdentifier {
     fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
         // Ignore whitespace
-        lexer.accept_run(" ");
+        lexer.accept_while(char::is_whitespace);
         lexer.ignore();
 
-        // Consume valid characters and emit
-        lexer.accept_run(VALID_IDENTIFIER_CHARACTER_SET);
-        lexer.emit(Token::Identifier(lexer.current()));
+        // Check if identifier is dynamic
+        if lexer.peekn(1) == "*" {
+            lexer.next();
+            lexer.emit(Token::Dynamic);
+        }
+
+        if !lexer.emit_identifier("") {
+            return None;
+        }
 
         return Some(Box::new(LexCloseRawDelimiter));
     }
@@ -257,7 +556,7 @@ struct LexCloseRawDelimiter;
 impl<R: Read + BufRead> State<R> for LexCloseRawDelimiter {
     fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
         // Ignore whitespace
-        lexer.accept_run(" ");
+        lexer.accept_while(char::is_whitespace);
         lexer.ignore();
 
         // Peek to see if we have reached a raw closing delimiter
@@ -280,27 +579,42 @@ struct LexNewDelimiter;
 impl<R: Read + BufRead> State<R> for LexNewDelimiter {
     fn next(&mut self, lexer: &mut Lexer<R>) -> StateFunction<R> {
         // Ignore whitespace
-        lexer.accept_run(" ");
+        lexer.accept_while(char::is_whitespace);
         lexer.ignore();
 
-        // Consume valid characters and set new open delimiter
-        lexer.accept_run(VALID_DELIMITER_CHARACTER_SET);
+        // Delimiters may be any characters other than whitespace and `=`
+        lexer.accept_while(is_delimiter_character);
         let new_open_delimiter = lexer.current();
         lexer.ignore();
 
-        if let (false, Some(got)) = lexer.accept(" ") {
-            return lexer.emit_error(LexerError::ExpectedCharacter(' ', got));
+        if new_open_delimiter.is_empty() {
+            return lexer.emit_error(LexerError::EmptyDelimiter);
         }
 
+        match lexer.next() {
+            Some(next_character) if next_character.is_whitespace() => {}
+            Some(got) => return lexer.emit_error(LexerError::ExpectedCharacter(' ', got)),
+            None => return lexer.emit_error(LexerError::UnexpectedEOF),
+        }
+
+        lexer.accept_while(char::is_whitespace);
         lexer.ignore();
 
-        // Consume valid characters and set new close delimiter
-        lexer.accept_run(VALID_DELIMITER_CHARACTER_SET);
+        lexer.accept_while(is_delimiter_character);
         let new_close_delimiter = lexer.current();
         lexer.ignore();
 
-        if let (false, Some(got)) = lexer.accept("=") {
-            return lexer.emit_error(LexerError::ExpectedCharacter('=', got));
+        if new_close_delimiter.is_empty() {
+            return lexer.emit_error(LexerError::EmptyDelimiter);
+        }
+
+        lexer.accept_while(char::is_whitespace);
+        lexer.ignore();
+
+        match lexer.accept("=") {
+            (true, _) => {}
+            (false, Some(got)) => return lexer.emit_error(LexerError::ExpectedCharacter('=', got)),
+            (false, None) => return lexer.emit_error(LexerError::UnexpectedEOF),
         }
 
         // Peek to see if we have reached the old closing delimiter
@@ -321,6 +635,10 @@ impl<R: Read + BufRead> State<R> for LexNewDelimiter {
     }
 }
 
+fn is_delimiter_character(character: char) -> bool {
+    return !character.is_whitespace() && character != '=';
+}
+
 impl<R: Read + BufRead> Lexer<R> {
     fn new(reader: R, sender: Sender<Token>) -> Self {
         let open_delimiter = String::from("{{");
@@ -338,32 +656,38 @@ impl<R: Read + BufRead> Lexer<R> {
             close_delimiter_chars: close_delimiter.chars().count(),
             raw_close_delimiter: String::from("}}}"),
             raw_close_delimiter_chars: 3,
+            partial_arguments: false,
+            escaped_delimiters: false,
         };
     }
 
-    fn next(&mut self) -> Option<char> {
-        match self.reader.fill_buf() {
+    /// Reads the whole template up front, so multi-byte characters are never split between
+    /// reads.
+    fn read(&mut self) -> Result<(), LexerError> {
+        let mut bytes = Vec::new();
+        if let Err(error) = self.reader.read_to_end(&mut bytes) {
+            return Err(LexerError::Read(error.to_string()));
+        }
+
+        return match String::from_utf8(bytes) {
             Ok(buffer) => {
-                let length = buffer.len();
-                if length > 0 {
-                    match std::str::from_utf8(buffer) {
-                        Ok(next_string) => {
-                            // println!("READ IN: {}", next_string);
-                            self.buffer.push_str(next_string);
-                        }
-                        Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
-                    }
-                }
-                self.reader.consume(length);
+                self.buffer = buffer;
+                Ok(())
+            }
+            Err(error) => {
+                let valid = error.utf8_error().valid_up_to();
+                // Report the position of the first invalid byte, which stands in as a
+                // replacement character
+                self.buffer = String::from_utf8_lossy(&error.as_bytes()[..=valid]).into_owned();
+                self.position = self.buffer.chars().count();
+                Err(LexerError::InvalidUtf8)
             }
-            Err(e) => panic!("Reading file: {}", e),
         };
+    }
 
-        if self.position >= self.buffer.len() {
-            return None;
-        }
-
-        let character = self.buffer.chars().nth(self.position).unwrap();
+    fn next(&mut self) -> Option<char> {
+        // Positions count characters rather than bytes
+        let character = self.buffer.chars().nth(self.position)?;
         self.position += 1;
 
         return Some(character);
@@ -432,17 +756,54 @@ impl<R: Read + BufRead> Lexer<R> {
             }
             return (false, Some(next_character));
         }
-        self.backup(1);
         return (false, None);
     }
 
-    fn accept_run(&mut self, character_set: &str) {
-        while let Some(next_characteracter) = self.next() {
-            if !character_set.contains(next_characteracter) {
-                break;
+    /// Consumes an identifier, which runs up to whitespace, the close delimiter or one of the
+    /// excluded characters.
+    fn accept_identifier(&mut self, excluded: &str) {
+        loop {
+            if self.peekn(self.close_delimiter_chars) == self.close_delimiter
+                || self.peekn(self.raw_close_delimiter_chars) == self.raw_close_delimiter
+            {
+                return;
+            }
+            match self.next() {
+                Some(next_character)
+                    if next_character.is_whitespace() || excluded.contains(next_character) =>
+                {
+                    self.backup(1);
+                    return;
+                }
+                Some(_) => {}
+                None => return,
+            }
+        }
+    }
+
+    /// Consumes and emits an identifier, emitting an error instead and returning false if there
+    /// is none.
+    fn emit_identifier(&mut self, excluded: &str) -> bool {
+        self.accept_identifier(excluded);
+        let identifier = self.current();
+        if identifier.is_empty() {
+            match self.next() {
+                Some(got) => self.emit_error(LexerError::ExpectedIdentifier(got)),
+                None => self.emit_error(LexerError::UnexpectedEOF),
+            };
+            return false;
+        }
+        self.emit(Token::Identifier(identifier));
+        return true;
+    }
+
+    fn accept_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while let Some(next_character) = self.next() {
+            if !predicate(next_character) {
+                self.backup(1);
+                return;
             }
         }
-        self.backup(1);
     }
 
     fn set_delimiters(&mut self, open_delimiter: String, close_delimiter: String) {
@@ -457,8 +818,10 @@ impl<R: Read + BufRead> Lexer<R> {
     }
 
     fn line(&self) -> usize {
-        return self.buffer[0..self.position]
+        return self
+            .buffer
             .chars()
+            .take(self.position)
             .filter(|character| character == &'\n')
             .count()
             + 1;
@@ -466,7 +829,8 @@ impl<R: Read + BufRead> Lexer<R> {
 
     fn column(&self) -> usize {
         let mut count = 0;
-        for character in self.buffer[0..self.position].chars().rev() {
+        let consumed = self.buffer.chars().take(self.position).collect::<Vec<_>>();
+        for character in consumed.into_iter().rev() {
             if character == '\n' {
                 break;
             }
@@ -1,100 +1,402 @@
+pub mod builder;
+pub mod check;
+pub mod dependencies;
+pub mod embed;
 pub mod encoder;
 pub mod lexer;
+pub mod loader;
+pub mod naming;
 pub mod node;
 pub mod parser;
+pub mod pragma;
+pub mod reload;
+pub mod sandbox;
 
-use std::{collections::HashMap, fs::File, io::BufReader, path::PathBuf};
+use std::{collections::HashMap, fs::File, io::BufReader, path::PathBuf, sync::Arc};
 
 use anyhow::{Error, Result};
+use builder::Parallelism;
+pub use builder::RustacheBuilder;
+use dependencies::{Dependencies, DependencyGraph};
 pub use encoder::Encoder;
 use glob::glob;
-use lexer::lex;
-use node::{Node, RenderError, Renderable, Value};
+use lexer::{lex_with_syntax, Syntax};
+use loader::{FileSystemLoader, LayeredLoader, Loader, MemoryLoader, Template};
+use naming::Naming;
+use node::{
+    render_fragment, Escaper, Fragment, Node, Partials, RenderError, RenderState, Renderable, Value,
+};
 use parser::{parse, ParserError};
+use pragma::Pragma;
+use sandbox::{DynamicNamePolicy, Sandbox};
 use serde::Serialize;
 
-#[derive(Debug)]
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// A registry of parsed templates. `Rustache` is `Send + Sync` and rendering only borrows it,
+/// so it can be wrapped in an `Arc` and rendered from many threads without locking.
+#[derive(Debug, Clone)]
 pub struct Rustache {
     pub directory: String,
-    pub partials: HashMap<String, Vec<Node>>,
+    pub partials: HashMap<String, Template>,
+    /// The maximum number of partials and parents that may be nested while rendering.
+    pub max_depth: usize,
+    /// Which templates dynamic partials and parents may resolve to.
+    pub dynamic_names: DynamicNamePolicy,
+    pub escaper: Escaper,
+    /// Whether missing identifiers are errors rather than rendering as empty.
+    pub strict: bool,
+    /// The syntax templates are parsed with, used again when they are reloaded.
+    pub syntax: Syntax,
+    /// Whether `items.length` resolves to the number of items in a list.
+    pub length_property: bool,
 }
 
+// Fails to compile if a field stops being shareable between threads
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Rustache>();
+};
+
 impl Rustache {
     pub fn new(directory: &str, glob_pattern: &str) -> Result<Self, Error> {
-        let available_threads = std::thread::available_parallelism().unwrap();
+        return Self::with_naming(directory, glob_pattern, &Naming::default());
+    }
 
-        let mut partials = HashMap::new();
+    /// Loads templates named by a custom [`Naming`] strategy.
+    pub fn with_naming(
+        directory: &str,
+        glob_pattern: &str,
+        naming: &Naming,
+    ) -> Result<Self, Error> {
+        return Self::builder(directory)
+            .glob(glob_pattern)
+            .naming(naming.clone())
+            .build();
+    }
 
-        let thread_pool = rayon::ThreadPoolBuilder::new()
-            .num_threads(available_threads.into())
-            .build()
-            .unwrap();
-
-        let (result_sender, result_reciever) =
-            crossbeam_channel::unbounded::<(String, Result<Vec<Node>, ParserError>)>();
-
-        for entry in
-            glob(&[directory, "/", glob_pattern].concat()).expect("failed to read glob pattern")
-        {
-            if let Ok(path) = entry {
-                let name = path
-                    .with_extension("")
-                    .iter()
-                    .skip(1)
-                    .collect::<PathBuf>()
-                    .to_str()
-                    .map(|s| s.to_string());
-
-                if name.is_none() {}
-
-                let (token_sender, token_reciever) = crossbeam_channel::bounded::<lexer::Token>(4);
-
-                let file = File::open(path).expect("failed to open file");
-
-                thread_pool.spawn(move || {
-                    let reader = BufReader::with_capacity(128, file);
-                    lex(reader, token_sender);
-                });
-
-                let result_producer = result_sender.clone();
-
-                thread_pool.spawn(move || {
-                    // if let Err(_) = result_producer.send((name.unwrap(), parse(token_reciever))) {}
-                    result_producer
-                        .send((name.unwrap(), parse(token_reciever)))
-                        .unwrap();
-                });
-            }
-        }
+    pub fn builder(directory: &str) -> RustacheBuilder {
+        return RustacheBuilder::new(directory);
+    }
 
-        drop(result_sender);
+    pub(crate) fn from_templates(
+        directory: &str,
+        templates: Vec<LoadedTemplate>,
+    ) -> Result<Self, Error> {
+        let mut partials = HashMap::new();
 
-        while let Ok((name, Ok(partial))) = result_reciever.recv() {
-            partials.insert(name, partial);
+        for template in templates {
+            match template.nodes {
+                Ok(nodes) => {
+                    let parsed = Template::parsed(&template.name, nodes)
+                        .with_origin(template.path.display().to_string());
+                    partials.insert(template.name, parsed);
+                }
+                Err(error) => {
+                    return Err(Error::new(error).context(format!(
+                        "failed to parse template: {}",
+                        template.path.display()
+                    )))
+                }
+            }
         }
 
         return Ok(Self {
             directory: directory.into(),
             partials,
+            max_depth: DEFAULT_MAX_DEPTH,
+            dynamic_names: DynamicNamePolicy::default(),
+            escaper: Escaper::default(),
+            strict: true,
+            syntax: Syntax::default(),
+            length_property: false,
         });
     }
 
+    /// Loads templates from an ordered list of directories. A template in an earlier directory
+    /// overrides a template of the same name in a later one, so `["tenant/acme", "base"]`
+    /// falls back to `base` for every template `tenant/acme` does not provide.
+    pub fn layered(directories: &[&str], glob_pattern: &str) -> Result<Self, Error> {
+        let mut loader = LayeredLoader::new();
+
+        for directory in directories {
+            loader = loader.layer(FileSystemLoader::new(directory, glob_pattern)?);
+        }
+
+        return Self::from_loader(loader);
+    }
+
+    /// Parses every template provided by a loader up front.
+    pub fn from_loader(loader: impl Loader + 'static) -> Result<Self, Error> {
+        return Self::builder("").build_from_loader(loader);
+    }
+
+    /// Parses templates embedded into the binary with [`include_views`].
+    pub fn embedded(templates: &'static [(&'static str, &'static str)]) -> Result<Self, Error> {
+        return Self::from_loader(MemoryLoader::new(templates.iter().copied()));
+    }
+
+    /// Resolves templates through a loader, parsing each one the first time it is rendered.
+    pub fn lazy(loader: impl Loader + 'static) -> Self {
+        return Self::builder("").lazy(loader);
+    }
+
+    pub(crate) fn lazy_with_syntax(loader: impl Loader + 'static, syntax: &Syntax) -> Self {
+        let loader: Arc<dyn Loader> = Arc::new(loader);
+
+        let partials = loader
+            .names()
+            .into_iter()
+            .map(|name| {
+                let template =
+                    Template::lazy(&name, loader.clone()).with_syntax(syntax.clone());
+                (name, template)
+            })
+            .collect();
+
+        return Self {
+            directory: String::new(),
+            partials,
+            max_depth: DEFAULT_MAX_DEPTH,
+            dynamic_names: DynamicNamePolicy::default(),
+            escaper: Escaper::default(),
+            strict: true,
+            syntax: Syntax::default(),
+            length_property: false,
+        };
+    }
+
     pub fn render<T>(
         &self,
         name: &str,
         writable: &mut impl std::io::Write,
         context: &T,
     ) -> Result<(), RenderError>
+    where
+        T: Serialize,
+    {
+        return self.render_sandboxed(name, writable, context, Sandbox::default());
+    }
+
+    /// Renders a template within the resource limits and allow-lists of a [`Sandbox`].
+    pub fn render_sandboxed<T>(
+        &self,
+        name: &str,
+        writable: &mut impl std::io::Write,
+        context: &T,
+        sandbox: Sandbox,
+    ) -> Result<(), RenderError>
+    where
+        T: Serialize,
+    {
+        return self.render_fragment(name, None, writable, context, sandbox);
+    }
+
+    /// Renders only the content of a block, resolved through the template's parents and
+    /// overrides as it would be when rendering the whole template. A block inside a section is
+    /// rendered with the section's context, once for each item of a list, and is not found when
+    /// the section renders nothing.
+    pub fn render_block<T>(
+        &self,
+        name: &str,
+        block: &str,
+        writable: &mut impl std::io::Write,
+        context: &T,
+    ) -> Result<(), RenderError>
+    where
+        T: Serialize,
+    {
+        let fragment = Fragment::Block(block.into());
+        return self.render_fragment(name, Some(fragment), writable, context, Sandbox::default());
+    }
+
+    /// Renders only a section, as it would be rendered within the whole template. The section
+    /// is searched for in the template, its parents, the blocks they resolve to and the
+    /// sections that render.
+    pub fn render_section<T>(
+        &self,
+        name: &str,
+        section: &str,
+        writable: &mut impl std::io::Write,
+        context: &T,
+    ) -> Result<(), RenderError>
+    where
+        T: Serialize,
+    {
+        let fragment = Fragment::Section(section.into());
+        return self.render_fragment(name, Some(fragment), writable, context, Sandbox::default());
+    }
+
+    fn render_fragment<T>(
+        &self,
+        name: &str,
+        fragment: Option<Fragment>,
+        writable: &mut impl std::io::Write,
+        context: &T,
+        sandbox: Sandbox,
+    ) -> Result<(), RenderError>
     where
         T: Serialize,
     {
         if !self.partials.contains_key(name) {
             return Err(RenderError::PartialDoesNotExist(name.into()));
         }
-        let partial = self.partials.get(name).unwrap();
+        let partial = self.partials.get(name).unwrap().nodes()?;
         let value = to_value(context).unwrap();
-        return partial.render(writable, &value, Some(&self.partials));
+        let mut state = RenderState::sandboxed(self.max_depth, sandbox);
+        state.dynamic_names = &self.dynamic_names;
+        state.escaper = &self.escaper;
+        state.strict = self.strict;
+        state.length_property = self.length_property;
+        if let Err(error) = state.enter(name) {
+            return Err(error);
+        }
+        state.enter_pragmas(partial)?;
+        let partials = Partials::new(&self.partials).with_template(name, partial);
+
+        let Some(fragment) = fragment else {
+            return partial.render(writable, &value, Some(&partials), &mut state);
+        };
+        if render_fragment(&fragment, partial, writable, &value, &partials, &mut state)? {
+            return Ok(());
+        }
+        return match fragment {
+            Fragment::Block(block) => Err(RenderError::BlockDoesNotExist {
+                template: name.into(),
+                block,
+            }),
+            Fragment::Section(section) => Err(RenderError::SectionDoesNotExist {
+                template: name.into(),
+                section,
+            }),
+        };
+    }
+
+    /// Where a template was loaded from, such as the path of its file.
+    pub fn origin(&self, name: &str) -> Option<String> {
+        return self
+            .partials
+            .get(name)
+            .and_then(|template| template.origin());
+    }
+
+    /// The index of the layer a template was loaded from when using layered loading.
+    pub fn layer(&self, name: &str) -> Option<usize> {
+        return self
+            .partials
+            .get(name)
+            .and_then(|template| template.layer());
+    }
+
+    /// The pragmas a template declares.
+    pub fn pragmas(&self, name: &str) -> Option<Vec<Pragma>> {
+        return self
+            .partials
+            .get(name)
+            .and_then(|template| template.pragmas().ok());
     }
+
+    /// The partials, parents, blocks, variables and sections referenced by a template.
+    pub fn dependencies(&self, name: &str) -> Option<Dependencies> {
+        return self
+            .partials
+            .get(name)
+            .and_then(|template| template.nodes().ok())
+            .map(|nodes| Dependencies::of(nodes).relative_to(name));
+    }
+
+    /// The dependencies of every loaded template.
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        return DependencyGraph::new(&self.partials);
+    }
+}
+
+/// A template file lexed and parsed from disk, along with its derived name.
+pub(crate) struct LoadedTemplate {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+    pub(crate) nodes: Result<Vec<Node>, ParserError>,
+}
+
+/// Lexes and parses a single template file on the current thread.
+pub(crate) fn parse_file(file: File, syntax: &Syntax) -> Result<Vec<Node>, ParserError> {
+    let (token_sender, token_reciever) = crossbeam_channel::unbounded::<lexer::Token>();
+    lex_with_syntax(BufReader::with_capacity(128, file), token_sender, syntax);
+    return parse(token_reciever);
+}
+
+pub(crate) fn load(
+    directory: &str,
+    glob_pattern: &str,
+    naming: &Naming,
+    syntax: &Syntax,
+) -> Result<Vec<LoadedTemplate>, Error> {
+    let paths = glob(&[directory, "/", glob_pattern].concat())?.filter_map(|entry| entry.ok());
+
+    let options = LoadOptions {
+        naming,
+        parallelism: &Parallelism::default(),
+        syntax,
+    };
+    return load_paths(directory, paths, &options);
+}
+
+/// How templates are named and lexed when loading them from disk.
+pub(crate) struct LoadOptions<'a> {
+    pub(crate) naming: &'a Naming,
+    pub(crate) parallelism: &'a Parallelism,
+    pub(crate) syntax: &'a Syntax,
+}
+
+/// Lexes and parses each named path in parallel, on the global rayon pool or a dedicated one.
+pub(crate) fn load_paths(
+    directory: &str,
+    paths: impl IntoIterator<Item = PathBuf>,
+    options: &LoadOptions,
+) -> Result<Vec<LoadedTemplate>, Error> {
+    let thread_pool = match options.parallelism.threads() {
+        Some(threads) => Some(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()?,
+        ),
+        None => None,
+    };
+
+    let spawn = |task: Box<dyn FnOnce() + Send>| match &thread_pool {
+        Some(thread_pool) => thread_pool.spawn(task),
+        None => rayon::spawn(task),
+    };
+
+    let (result_sender, result_reciever) = crossbeam_channel::unbounded::<LoadedTemplate>();
+
+    for (name, path) in options.naming.names(directory, paths)? {
+        let (token_sender, token_reciever) = crossbeam_channel::unbounded::<lexer::Token>();
+
+        let file = File::open(&path)?;
+        let syntax = options.syntax.clone();
+
+        spawn(Box::new(move || {
+            let reader = BufReader::with_capacity(128, file);
+            lex_with_syntax(reader, token_sender, &syntax);
+        }));
+
+        let result_producer = result_sender.clone();
+
+        spawn(Box::new(move || {
+            result_producer
+                .send(LoadedTemplate {
+                    name,
+                    path,
+                    nodes: parse(token_reciever),
+                })
+                .unwrap();
+        }));
+    }
+
+    drop(result_sender);
+
+    return Ok(result_reciever.iter().collect());
 }
 
 pub fn to_value<T>(value: T) -> std::result::Result<Value, encoder::Error>
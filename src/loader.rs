@@ -0,0 +1,298 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
+
+use anyhow::{Error, Result};
+use glob::glob;
+
+use crate::{
+    lexer::{lex_with_syntax, Syntax, Token},
+    naming::Naming,
+    node::{Node, RenderError},
+    parser::{parse, ParserError},
+    pragma::{pragmas, Pragma},
+};
+
+/// A source of template files that a [`crate::Rustache`] resolves names through.
+pub trait Loader: Send + Sync {
+    /// The names of every template this loader can provide.
+    fn names(&self) -> Vec<String>;
+
+    /// Reads the source of a template, or `None` if this loader does not provide it.
+    fn source(&self, name: &str) -> Result<Option<String>, Error>;
+
+    fn contains(&self, name: &str) -> bool {
+        return self.names().iter().any(|other| other == name);
+    }
+
+    /// Where a template was loaded from, used when reporting errors.
+    fn origin(&self, name: &str) -> Option<String> {
+        return self.contains(name).then(|| name.into());
+    }
+
+    /// The index of the layer a template is loaded from, for loaders made of layers.
+    fn layer(&self, _name: &str) -> Option<usize> {
+        return None;
+    }
+}
+
+/// Loads templates matching a glob pattern from a directory on disk.
+#[derive(Debug, Clone)]
+pub struct FileSystemLoader {
+    pub directory: String,
+    paths: HashMap<String, PathBuf>,
+}
+
+impl FileSystemLoader {
+    pub fn new(directory: &str, glob_pattern: &str) -> Result<Self, Error> {
+        return Self::with_naming(directory, glob_pattern, &Naming::default());
+    }
+
+    pub fn with_naming(
+        directory: &str,
+        glob_pattern: &str,
+        naming: &Naming,
+    ) -> Result<Self, Error> {
+        let paths = glob(&[directory, "/", glob_pattern].concat())?.filter_map(|entry| entry.ok());
+        let paths = naming.names(directory, paths)?;
+
+        return Ok(Self {
+            directory: directory.into(),
+            paths,
+        });
+    }
+}
+
+impl Loader for FileSystemLoader {
+    fn names(&self) -> Vec<String> {
+        return self.paths.keys().cloned().collect();
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        return self.paths.contains_key(name);
+    }
+
+    fn source(&self, name: &str) -> Result<Option<String>, Error> {
+        return match self.paths.get(name) {
+            Some(path) => Ok(Some(std::fs::read_to_string(path)?)),
+            None => Ok(None),
+        };
+    }
+
+    fn origin(&self, name: &str) -> Option<String> {
+        return self
+            .paths
+            .get(name)
+            .map(|path| path.display().to_string());
+    }
+}
+
+/// Serves templates from sources held in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLoader {
+    pub templates: HashMap<String, String>,
+}
+
+impl MemoryLoader {
+    pub fn new<I, N, S>(templates: I) -> Self
+    where
+        I: IntoIterator<Item = (N, S)>,
+        N: Into<String>,
+        S: Into<String>,
+    {
+        return Self {
+            templates: templates
+                .into_iter()
+                .map(|(name, source)| (name.into(), source.into()))
+                .collect(),
+        };
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.templates.insert(name.into(), source.into());
+    }
+}
+
+impl Loader for MemoryLoader {
+    fn names(&self) -> Vec<String> {
+        return self.templates.keys().cloned().collect();
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        return self.templates.contains_key(name);
+    }
+
+    fn source(&self, name: &str) -> Result<Option<String>, Error> {
+        return Ok(self.templates.get(name).cloned());
+    }
+}
+
+/// Resolves each name through an ordered list of loaders, the first loader providing a
+/// template wins.
+#[derive(Default)]
+pub struct LayeredLoader {
+    pub layers: Vec<Box<dyn Loader>>,
+}
+
+impl LayeredLoader {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn layer(mut self, loader: impl Loader + 'static) -> Self {
+        self.layers.push(Box::new(loader));
+        return self;
+    }
+}
+
+impl Loader for LayeredLoader {
+    fn names(&self) -> Vec<String> {
+        return self
+            .layers
+            .iter()
+            .flat_map(|layer| layer.names())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        return self.layers.iter().any(|layer| layer.contains(name));
+    }
+
+    fn source(&self, name: &str) -> Result<Option<String>, Error> {
+        return match self.layers.iter().find(|layer| layer.contains(name)) {
+            Some(layer) => layer.source(name),
+            None => Ok(None),
+        };
+    }
+
+    fn origin(&self, name: &str) -> Option<String> {
+        return self
+            .layers
+            .iter()
+            .find(|layer| layer.contains(name))
+            .and_then(|layer| layer.origin(name));
+    }
+
+    fn layer(&self, name: &str) -> Option<usize> {
+        return self.layers.iter().position(|layer| layer.contains(name));
+    }
+}
+
+/// A template in a registry, either parsed up front or parsed through its loader the first
+/// time it is rendered.
+#[derive(Clone)]
+pub struct Template {
+    name: String,
+    origin: Option<String>,
+    loader: Option<Arc<dyn Loader>>,
+    syntax: Syntax,
+    nodes: OnceLock<Result<Vec<Node>, String>>,
+}
+
+impl Template {
+    pub fn parsed(name: &str, nodes: Vec<Node>) -> Self {
+        return Self {
+            name: name.into(),
+            origin: None,
+            loader: None,
+            syntax: Syntax::default(),
+            nodes: OnceLock::from(Ok(nodes)),
+        };
+    }
+
+    pub fn lazy(name: &str, loader: Arc<dyn Loader>) -> Self {
+        return Self {
+            name: name.into(),
+            origin: None,
+            loader: Some(loader),
+            syntax: Syntax::default(),
+            nodes: OnceLock::new(),
+        };
+    }
+
+    /// The syntax a lazily loaded template is parsed with.
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        return self;
+    }
+
+    pub fn with_origin(mut self, origin: impl Into<String>) -> Self {
+        self.origin = Some(origin.into());
+        return self;
+    }
+
+    /// Where the template was loaded from, such as the path of its file.
+    pub fn origin(&self) -> Option<String> {
+        return self.origin.clone().or_else(|| {
+            self.loader
+                .as_ref()
+                .and_then(|loader| loader.origin(&self.name))
+        });
+    }
+
+    /// The index of the layer the template was loaded from when using a [`LayeredLoader`].
+    pub fn layer(&self) -> Option<usize> {
+        return self
+            .loader
+            .as_ref()
+            .and_then(|loader| loader.layer(&self.name));
+    }
+
+    /// The pragmas declared by the template, parsing it if it has not been yet.
+    pub fn pragmas(&self) -> Result<Vec<Pragma>, RenderError> {
+        return self.nodes().map(|nodes| pragmas(nodes).cloned().collect());
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        return self.nodes.get().is_some();
+    }
+
+    /// The parsed nodes of the template, loading and parsing it if it has not been yet.
+    pub fn nodes(&self) -> Result<&Vec<Node>, RenderError> {
+        let nodes = self.nodes.get_or_init(|| {
+            let Some(loader) = &self.loader else {
+                return Err(format!("template: '{}' has no loader", self.name));
+            };
+            return match loader.source(&self.name) {
+                Ok(Some(source)) => parse_source_with_syntax(&source, &self.syntax)
+                    .map_err(|error| {
+                        let origin = loader.origin(&self.name).unwrap_or(self.name.clone());
+                        format!("{}:{}", origin, error)
+                    }),
+                Ok(None) => Err(format!("template: '{}' does not exist", self.name)),
+                Err(error) => Err(error.to_string()),
+            };
+        });
+
+        return match nodes {
+            Ok(nodes) => Ok(nodes),
+            Err(message) => Err(RenderError::TemplateLoadFailed(message.clone())),
+        };
+    }
+}
+
+impl std::fmt::Debug for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return f
+            .debug_struct("Template")
+            .field("name", &self.name)
+            .field("origin", &self.origin())
+            .field("nodes", &self.nodes.get())
+            .finish();
+    }
+}
+
+/// Lexes and parses template source held in memory.
+pub fn parse_source(source: &str) -> Result<Vec<Node>, ParserError> {
+    return parse_source_with_syntax(source, &Syntax::default());
+}
+
+pub fn parse_source_with_syntax(source: &str, syntax: &Syntax) -> Result<Vec<Node>, ParserError> {
+    let (token_sender, token_reciever) = crossbeam_channel::unbounded::<Token>();
+    lex_with_syntax(source.as_bytes(), token_sender, syntax);
+    return parse(token_reciever);
+}
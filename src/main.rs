@@ -1,16 +1,137 @@
-use rustache::Rustache;
-use serde::Serialize;
-
-#[derive(Serialize)]
-struct MyData {
-  name: String,
-  activity: String,
-  address: String,
-  smell: String
+use std::process::ExitCode;
+
+use rustache::{
+    check::check_with_syntax,
+    lexer::{Delimiters, Syntax},
+    Rustache,
+};
+
+const USAGE: &str = "usage:
+    rustache check [--views <directory>] [--glob <pattern>] [--deny-unreferenced] [syntax]
+    rustache graph [--views <directory>] [--glob <pattern>] [--format dot|json] [syntax]
+
+syntax options, which must match how the templates are rendered:
+    --delimiters <open> <close>    the delimiters templates start with
+    --partial-arguments            partial tags take named arguments
+    --escaped-delimiters           a backslash before an open delimiter keeps it as text";
+
+struct Options {
+    views: String,
+    glob_pattern: String,
+    deny_unreferenced: bool,
+    format: String,
+    syntax: Syntax,
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+
+    let mut options = Options {
+        views: String::from("views"),
+        glob_pattern: String::from("**/*.mustache"),
+        deny_unreferenced: false,
+        format: String::from("dot"),
+        syntax: Syntax::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--deny-unreferenced" => {
+                options.deny_unreferenced = true;
+                continue;
+            }
+            "--partial-arguments" => {
+                options.syntax.partial_arguments = true;
+                continue;
+            }
+            "--escaped-delimiters" => {
+                options.syntax.escaped_delimiters = true;
+                continue;
+            }
+            "--delimiters" => match (args.next(), args.next()) {
+                (Some(open), Some(close)) => {
+                    options.syntax.delimiters = Delimiters::new(open, close);
+                    continue;
+                }
+                _ => None,
+            },
+            "--views" | "--glob" | "--format" => args.next(),
+            _ => None,
+        };
+
+        match (arg.as_str(), value) {
+            ("--views", Some(value)) => options.views = value.trim_end_matches('/').into(),
+            ("--glob", Some(value)) => options.glob_pattern = value,
+            ("--format", Some(value)) => options.format = value,
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    return match command.as_deref() {
+        Some("check") => run_check(&options),
+        Some("graph") => run_graph(&options),
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+    };
 }
 
-fn main() {
-  let mut stdout = std::io::stdout();
-  let rustache = Rustache::new("views", "**/*.mustache").expect("failed to parse template files");
-  rustache.render("lily", &mut stdout, &MyData{ name: "lily".into(), activity: "skip".into(), address: "disney world".into(), smell: "rose petals".into() }).expect("failed to render template");
-}
\ No newline at end of file
+fn run_check(options: &Options) -> ExitCode {
+    let report = match check_with_syntax(&options.views, &options.glob_pattern, &options.syntax) {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("error: {:#}", error);
+            return ExitCode::from(2);
+        }
+    };
+
+    for diagnostic in &report.diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+
+    let errors = report.errors().count();
+    let warnings = report.warnings().count();
+
+    eprintln!("{} error(s), {} warning(s)", errors, warnings);
+
+    if errors > 0 || (options.deny_unreferenced && warnings > 0) {
+        return ExitCode::FAILURE;
+    }
+
+    return ExitCode::SUCCESS;
+}
+
+fn run_graph(options: &Options) -> ExitCode {
+    let mut builder = Rustache::builder(&options.views).glob(&options.glob_pattern);
+    builder.syntax = options.syntax.clone();
+
+    let rustache = match builder.build() {
+        Ok(rustache) => rustache,
+        Err(error) => {
+            eprintln!("error: {:#}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let graph = rustache.dependency_graph();
+
+    match options.format.as_str() {
+        "dot" => print!("{}", graph.to_dot()),
+        "json" => println!("{}", graph.to_json()),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    }
+
+    for cycle in graph.cycles() {
+        eprintln!("warning: include cycle: {}", cycle.join(" -> "));
+    }
+
+    return ExitCode::SUCCESS;
+}
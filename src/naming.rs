@@ -0,0 +1,151 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum NamingError {
+    #[error("template: '{name}' is provided by both {} and {}", .first.display(), .second.display())]
+    Collision {
+        name: String,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    #[error("alias: '{alias}' collides with an existing template")]
+    AliasCollision { alias: String },
+    #[error("alias: '{alias}' refers to template: '{name}' which does not exist")]
+    AliasTargetDoesNotExist { alias: String, name: String },
+}
+
+/// Derives template names from file paths. By default a file's name is its path relative
+/// to the views directory without its extension, so `views/partials/header.mustache` is
+/// named `partials/header`.
+#[derive(Debug, Clone, Default)]
+pub struct Naming {
+    pub keep_extensions: bool,
+    /// Additional names for templates, mapping each alias to the name it stands for.
+    pub aliases: HashMap<String, String>,
+}
+
+impl Naming {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Keeps file extensions in template names, so `foo.mustache` and `foo.html` can coexist.
+    pub fn keep_extensions(mut self, keep_extensions: bool) -> Self {
+        self.keep_extensions = keep_extensions;
+        return self;
+    }
+
+    pub fn alias(mut self, alias: impl Into<String>, name: impl Into<String>) -> Self {
+        self.aliases.insert(alias.into(), name.into());
+        return self;
+    }
+
+    /// The name of the template at `path` inside `directory`.
+    pub fn name(&self, directory: &str, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(normalize(directory)).ok()?;
+
+        let relative = match self.keep_extensions {
+            true => relative.to_path_buf(),
+            false => relative.with_extension(""),
+        };
+
+        return relative
+            .iter()
+            .map(|part| part.to_str())
+            .collect::<Option<Vec<_>>>()
+            .map(|parts| parts.join("/"));
+    }
+
+    /// Names every path and adds its aliases, reporting names that would be shared.
+    pub fn names(
+        &self,
+        directory: &str,
+        paths: impl IntoIterator<Item = PathBuf>,
+    ) -> Result<HashMap<String, PathBuf>, NamingError> {
+        let mut names: HashMap<String, PathBuf> = HashMap::new();
+
+        for path in paths {
+            let Some(name) = self.name(directory, &path) else {
+                continue;
+            };
+
+            if let Some(first) = names.get(&name) {
+                let (first, second) = match first < &path {
+                    true => (first.clone(), path),
+                    false => (path, first.clone()),
+                };
+                return Err(NamingError::Collision {
+                    name,
+                    first,
+                    second,
+                });
+            }
+
+            names.insert(name, path);
+        }
+
+        for alias in self.aliases.keys() {
+            if names.contains_key(alias) {
+                return Err(NamingError::AliasCollision {
+                    alias: alias.clone(),
+                });
+            }
+        }
+
+        let mut aliases = Vec::new();
+
+        for (alias, name) in &self.aliases {
+            match names.get(name) {
+                Some(path) => aliases.push((alias.clone(), path.clone())),
+                None => {
+                    return Err(NamingError::AliasTargetDoesNotExist {
+                        alias: alias.clone(),
+                        name: name.clone(),
+                    })
+                }
+            }
+        }
+
+        names.extend(aliases);
+
+        return Ok(names);
+    }
+}
+
+/// Resolves a `./` or `../` reference against the name of the template containing it, so
+/// `../shared/footer` in `pages/index` names `shared/footer`. Other names are returned
+/// unchanged and `None` means the reference escapes above the root.
+pub fn resolve_relative(from: &str, name: &str) -> Option<String> {
+    if !name.starts_with("./") && !name.starts_with("../") {
+        return Some(name.into());
+    }
+
+    let mut parts = from.split('/').collect::<Vec<_>>();
+    parts.pop();
+
+    for part in name.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+
+    return Some(parts.join("/"));
+}
+
+/// Glob strips `./` components from the paths it returns, so they are dropped from the
+/// directory too.
+fn normalize(directory: &str) -> PathBuf {
+    return Path::new(directory)
+        .components()
+        .filter(|component| *component != Component::CurDir)
+        .collect();
+}
@@ -1,5 +1,12 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap, sync::Arc, time::{Duration, Instant}};
 use thiserror::Error;
+
+use crate::{
+    loader::Template,
+    naming::resolve_relative,
+    pragma::{pragmas, EscapeMode, Pragma},
+    sandbox::{DynamicNamePolicy, Sandbox},
+};
 use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,12 +44,340 @@ impl Value {
     }
 }
 
+/// Escapes the values of `{{name}}` tags, `{{{name}}}` and `{{&name}}` are never escaped.
+#[derive(Clone, Default)]
+pub enum Escaper {
+    #[default]
+    Html,
+    None,
+    Custom(Arc<dyn Fn(&str) -> String + Send + Sync>),
+}
+
+impl Escaper {
+    pub fn custom(escaper: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        return Self::Custom(Arc::new(escaper));
+    }
+
+    pub fn escape<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        return match self {
+            Escaper::Html => html_escape::encode_text(value),
+            Escaper::None => Cow::Borrowed(value),
+            Escaper::Custom(escaper) => Cow::Owned(escaper(value)),
+        };
+    }
+}
+
+impl std::fmt::Debug for Escaper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Escaper::Html => write!(f, "Html"),
+            Escaper::None => write!(f, "None"),
+            Escaper::Custom(_) => write!(f, "Custom(..)"),
+        };
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum RenderError {
     #[error("identifier: '{0}' does not exist")]
     IdentifierDoesNotExist(String),
     #[error("partial: '{0}' does not exist")]
     PartialDoesNotExist(String),
+    #[error("partial depth limit of {limit} exceeded: {}", .chain.join(" -> "))]
+    DepthLimitExceeded { limit: usize, chain: Vec<String> },
+    #[error("output limit of {0} bytes exceeded")]
+    OutputLimitExceeded(usize),
+    #[error("evaluation limit of {0} nodes exceeded")]
+    EvaluationLimitExceeded(usize),
+    #[error("render timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("render was cancelled")]
+    Cancelled,
+    #[error("identifier: '{0}' is not allowed")]
+    IdentifierNotAllowed(String),
+    #[error("partial: '{0}' is not allowed")]
+    PartialNotAllowed(String),
+    #[error("pragma: '{0}' is not allowed")]
+    PragmaNotAllowed(String),
+    #[error("dynamic name: '{name}' from identifier: '{identifier}' is not allowed")]
+    DynamicNameNotAllowed { identifier: String, name: String },
+    #[error("partial: '{0}' escapes the views directory")]
+    PartialEscapesRoot(String),
+    #[error("block: '{block}' does not exist in template: '{template}'")]
+    BlockDoesNotExist { template: String, block: String },
+    #[error("section: '{section}' does not exist in template: '{template}'")]
+    SectionDoesNotExist { template: String, section: String },
+    #[error("failed to load template: {0}")]
+    TemplateLoadFailed(String),
+    #[error("failed to write output: {0}")]
+    Write(#[from] std::io::Error),
+}
+
+/// A block or section rendered on its own, see [`crate::Rustache::render_block`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Fragment {
+    Block(String),
+    Section(String),
+}
+
+/// Tracks the chain of partials and parents being expanded during a render so runaway
+/// recursion is reported as an error instead of overflowing the stack, along with the
+/// resources consumed so far when rendering inside a [`Sandbox`].
+#[derive(Debug, Clone)]
+pub struct RenderState<'a> {
+    pub max_depth: usize,
+    pub sandbox: Sandbox,
+    pub dynamic_names: &'a DynamicNamePolicy,
+    pub escaper: &'a Escaper,
+    /// Whether missing identifiers are errors rather than rendering as empty.
+    pub strict: bool,
+    /// Whether `items.length` resolves to the number of items in a list.
+    pub length_property: bool,
+    /// The name `{{%IMPLICIT-ITERATOR}}` gives the current context, alongside `.`.
+    pub iterator: Option<String>,
+    /// Whether `{{%ANCHORED-DOT}}` anchors `{{.name}}` to the current context.
+    pub anchored_dot: bool,
+    /// The settings templates without pragmas render with, captured on the first template.
+    defaults: Option<Settings<'a>>,
+    chain: Vec<String>,
+    /// The keys of the sections being rendered, outermost first, so the sandbox can check the
+    /// full path of each lookup.
+    sections: Vec<String>,
+    /// The arguments of the partials being rendered, innermost last.
+    frames: Vec<Frame>,
+    output_bytes: usize,
+    evaluations: usize,
+    deadline: Option<Instant>,
+}
+
+static ANY_DYNAMIC_NAME: DynamicNamePolicy = DynamicNamePolicy::Any;
+static HTML_ESCAPER: Escaper = Escaper::Html;
+static NO_ESCAPER: Escaper = Escaper::None;
+static MISSING: Value = Value::None;
+
+impl<'a> RenderState<'a> {
+    pub fn new(max_depth: usize) -> Self {
+        return Self::sandboxed(max_depth, Sandbox::default());
+    }
+
+    pub fn sandboxed(max_depth: usize, sandbox: Sandbox) -> Self {
+        return Self {
+            max_depth: sandbox.max_depth.map_or(max_depth, |depth| depth.min(max_depth)),
+            dynamic_names: &ANY_DYNAMIC_NAME,
+            escaper: &HTML_ESCAPER,
+            strict: true,
+            length_property: false,
+            iterator: None,
+            anchored_dot: false,
+            defaults: None,
+            deadline: sandbox.timeout.map(|timeout| Instant::now() + timeout),
+            sandbox,
+            chain: Vec::new(),
+            sections: Vec::new(),
+            frames: Vec::new(),
+            output_bytes: 0,
+            evaluations: 0,
+        };
+    }
+
+    /// Counts a node evaluation against the sandbox limits.
+    pub(crate) fn evaluate(&mut self) -> Result<(), RenderError> {
+        self.evaluations += 1;
+
+        if let Some(max_evaluations) = self.sandbox.max_evaluations {
+            if self.evaluations > max_evaluations {
+                return Err(RenderError::EvaluationLimitExceeded(max_evaluations));
+            }
+        }
+
+        if let Some(deadline) = self.deadline {
+            if Instant::now() > deadline {
+                return Err(RenderError::Timeout(self.sandbox.timeout.unwrap_or_default()));
+            }
+        }
+
+        if let Some(cancellation) = &self.sandbox.cancellation {
+            if cancellation.is_cancelled() {
+                return Err(RenderError::Cancelled);
+            }
+        }
+
+        return Ok(());
+    }
+
+    pub(crate) fn write(
+        &mut self,
+        writable: &mut impl std::io::Write,
+        bytes: &[u8],
+    ) -> Result<(), RenderError> {
+        self.output_bytes += bytes.len();
+
+        if let Some(max_output_bytes) = self.sandbox.max_output_bytes {
+            if self.output_bytes > max_output_bytes {
+                return Err(RenderError::OutputLimitExceeded(max_output_bytes));
+            }
+        }
+
+        return writable.write_all(bytes).map_err(RenderError::from);
+    }
+
+    /// Maps a template name looked up from the context through the dynamic name policy.
+    pub(crate) fn dynamic_name(&self, identifier: &str, name: &str) -> Result<String, RenderError> {
+        return match self.dynamic_names.resolve(name) {
+            Some(name) => Ok(name),
+            None => Err(RenderError::DynamicNameNotAllowed {
+                identifier: identifier.into(),
+                name: name.into(),
+            }),
+        };
+    }
+
+    /// Checks an identifier against the allowed keys, beneath the sections it is nested in.
+    pub(crate) fn allow_identifier(
+        &self,
+        identifier: &str,
+        section: bool,
+    ) -> Result<(), RenderError> {
+        if self.sandbox.allowed_keys.is_none() {
+            return Ok(());
+        }
+        let mut path = self.sections.join(".");
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(identifier);
+        let allowed = match section {
+            true => self.sandbox.allows_section(&path),
+            false => self.sandbox.allows_key(&path),
+        };
+        if !allowed {
+            return Err(RenderError::IdentifierNotAllowed(path));
+        }
+        return Ok(());
+    }
+
+    fn enter_section(&mut self, key: &str) {
+        self.sections.push(key.strip_prefix('.').unwrap_or(key).into());
+    }
+
+    fn exit_section(&mut self) {
+        self.sections.pop();
+    }
+
+    /// Looks a name up in the arguments of the partials being rendered. Arguments are only
+    /// visible until a section changes the context.
+    fn argument(&self, identifier: &str) -> Option<Value> {
+        let (name, rest) = match identifier.split_once('.') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (identifier, None),
+        };
+        let depth = self.sections.len();
+
+        for frame in self.frames.iter().rev().take_while(|frame| frame.depth == depth) {
+            if let Some(value) = frame.arguments.get(name) {
+                return match rest {
+                    Some(rest) => lookup(rest, value, self.length_property).map(|value| value.into_owned()),
+                    None => Some(value.clone()),
+                };
+            }
+        }
+
+        return None;
+    }
+
+    pub(crate) fn enter(&mut self, name: &str) -> Result<(), RenderError> {
+        if self.chain.len() >= self.max_depth {
+            // Name the cycle if there is one rather than the whole chain
+            let start = self
+                .chain
+                .iter()
+                .rposition(|other| other == name)
+                .unwrap_or(0);
+            let mut chain = self.chain[start..].to_vec();
+            chain.push(name.into());
+            return Err(RenderError::DepthLimitExceeded {
+                limit: self.max_depth,
+                chain,
+            });
+        }
+        self.chain.push(name.into());
+        return Ok(());
+    }
+
+    pub(crate) fn exit(&mut self) {
+        self.chain.pop();
+    }
+
+    fn settings(&self) -> Settings<'a> {
+        return Settings {
+            escaper: self.escaper,
+            strict: self.strict,
+            iterator: self.iterator.clone(),
+            anchored_dot: self.anchored_dot,
+        };
+    }
+
+    fn restore(&mut self, settings: Settings<'a>) {
+        self.escaper = settings.escaper;
+        self.strict = settings.strict;
+        self.iterator = settings.iterator;
+        self.anchored_dot = settings.anchored_dot;
+    }
+
+    /// Applies the pragmas of a template over the default settings, returning the settings to
+    /// restore once the template has been rendered.
+    pub(crate) fn enter_pragmas(&mut self, nodes: &[Node]) -> Result<Settings<'a>, RenderError> {
+        for pragma in pragmas(nodes) {
+            if !self.sandbox.allows_pragma(pragma.name()) {
+                return Err(RenderError::PragmaNotAllowed(pragma.name().into()));
+            }
+        }
+
+        let previous = self.settings();
+        let defaults = self.defaults.get_or_insert_with(|| previous.clone()).clone();
+        let escaper = defaults.escaper;
+        self.restore(defaults);
+
+        for pragma in pragmas(nodes) {
+            match pragma {
+                Pragma::ImplicitIterator(iterator) => self.iterator = Some(iterator.clone()),
+                Pragma::AnchoredDot => self.anchored_dot = true,
+                Pragma::Strict => self.strict = true,
+                Pragma::Lenient => self.strict = false,
+                // A custom escaper is kept, `html` only turns escaping back on
+                Pragma::Escape(EscapeMode::Html) => {
+                    self.escaper = match escaper {
+                        Escaper::None => &HTML_ESCAPER,
+                        escaper => escaper,
+                    }
+                }
+                Pragma::Escape(EscapeMode::None) => self.escaper = &NO_ESCAPER,
+            }
+        }
+
+        return Ok(previous);
+    }
+
+    pub(crate) fn exit_pragmas(&mut self, previous: Settings<'a>) {
+        self.restore(previous);
+    }
+}
+
+/// The arguments of a partial, layered over the context it is rendered with.
+#[derive(Debug, Clone)]
+struct Frame {
+    /// The number of sections entered when the partial was rendered.
+    depth: usize,
+    arguments: HashMap<String, Value>,
+}
+
+/// The render settings pragmas can change.
+#[derive(Debug, Clone)]
+pub(crate) struct Settings<'a> {
+    escaper: &'a Escaper,
+    strict: bool,
+    iterator: Option<String>,
+    anchored_dot: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -50,11 +385,15 @@ pub enum Node {
     Section {
         identifier: String,
         inverted: bool,
+        /// Whether the identifier names the key to look up, `{{#*section}}`.
+        dynamic: bool,
         children: Vec<Node>,
     },
     Variable {
         identifier: String,
         escaped: bool,
+        /// Whether the identifier names the key to look up, `{{*field}}`.
+        dynamic: bool,
     },
     Text(String),
     Implicit,
@@ -62,6 +401,8 @@ pub enum Node {
     Partial {
         identifier: String,
         dynamic: bool,
+        /// Named values pushed as a new context frame, `{{> card title=post.title}}`.
+        arguments: Vec<(String, Argument)>,
     },
     Block {
         identifier: String,
@@ -72,6 +413,171 @@ pub enum Node {
         dynamic: bool,
         children: Vec<Node>,
     },
+    /// `{{@super}}` inside a block override, rendering the content it overrides.
+    Super,
+    /// A partial defined inline with `{{*define name}}`, rendering nothing where it appears.
+    Definition {
+        identifier: String,
+        children: Vec<Node>,
+    },
+    /// A `{{%NAME}}` pragma, applying to the whole template it appears in.
+    Pragma(Pragma),
+}
+
+/// The block overrides of one parent tag, linked to the overrides of the parent tags that
+/// are expanding it.
+#[derive(Debug)]
+struct BlockScope<'a> {
+    overrides: &'a [Node],
+    /// The name and nodes of the template the overrides were written in.
+    template: &'a str,
+    definitions: &'a [Node],
+    outer: Option<&'a BlockScope<'a>>,
+}
+
+impl<'a> BlockScope<'a> {
+    /// The overrides of a parent tag written in the template `partials` are rendering.
+    fn new(overrides: &'a [Node], partials: &Partials<'a>) -> Self {
+        return Self {
+            overrides,
+            template: partials.template,
+            definitions: partials.definitions,
+            outer: partials.blocks,
+        };
+    }
+}
+
+/// Content a block can render, with the name and nodes of the template it was written in so
+/// relative names and partials defined there resolve as they would in that template.
+#[derive(Debug, Clone, Copy)]
+struct BlockContent<'a> {
+    nodes: &'a Vec<Node>,
+    template: &'a str,
+    definitions: &'a [Node],
+}
+
+/// The templates and block overrides visible while rendering. Blocks live in their own scope,
+/// so an override named `header` never replaces `{{> header}}`.
+#[derive(Debug, Clone, Copy)]
+pub struct Partials<'a> {
+    pub registry: &'a HashMap<String, Template>,
+    /// The name of the template that wrote the nodes being rendered.
+    template: &'a str,
+    /// The nodes of that template, searched for inline partial definitions.
+    definitions: &'a [Node],
+    blocks: Option<&'a BlockScope<'a>>,
+    /// The content `{{@super}}` renders within the block being rendered, nearest first.
+    supers: &'a [BlockContent<'a>],
+}
+
+impl<'a> Partials<'a> {
+    pub fn new(registry: &'a HashMap<String, Template>) -> Self {
+        return Self {
+            registry,
+            template: "",
+            definitions: &[],
+            blocks: None,
+            supers: &[],
+        };
+    }
+
+    /// Renders the nodes of the template `name`, making the partials defined at their top
+    /// level visible and resolving relative names against it.
+    pub fn with_template(mut self, name: &'a str, nodes: &'a [Node]) -> Self {
+        self.template = name;
+        self.definitions = nodes;
+        return self;
+    }
+
+    pub fn get(&self, name: &str) -> Option<&'a Template> {
+        return self.registry.get(name);
+    }
+
+    /// Resolves a partial or parent name relative to the template that wrote the tag.
+    fn template_name(&self, name: &str) -> Result<String, RenderError> {
+        return match resolve_relative(self.template, name) {
+            Some(name) => Ok(name),
+            None => Err(RenderError::PartialEscapesRoot(name.into())),
+        };
+    }
+
+    /// A partial defined with `{{*define name}}` in the template being rendered.
+    pub fn definition(&self, name: &str) -> Option<&'a Vec<Node>> {
+        return self.definitions.iter().find_map(|node| match node {
+            Node::Definition {
+                identifier,
+                children,
+            } if identifier == name => Some(children),
+            _ => None,
+        });
+    }
+
+    /// The overrides of a block, outermost first. With several levels of inheritance the
+    /// outermost parent tag wins, so a page overrides the blocks its layout sets for a base
+    /// layout, and each override can render the next one with `{{@super}}`.
+    fn blocks(&self, name: &str) -> Vec<BlockContent<'a>> {
+        let mut found = Vec::new();
+        let mut scope = self.blocks;
+
+        while let Some(current) = scope {
+            // Later overrides of the same block within a parent tag win
+            for node in current.overrides.iter().rev() {
+                if let Node::Block {
+                    identifier,
+                    children,
+                } = node
+                {
+                    if identifier == name {
+                        found.push(BlockContent {
+                            nodes: children,
+                            template: current.template,
+                            definitions: current.definitions,
+                        });
+                        break;
+                    }
+                }
+            }
+            scope = current.outer;
+        }
+
+        found.reverse();
+        return found;
+    }
+
+    /// The candidate contents of a block, its overrides outermost first and then its own
+    /// content.
+    fn candidates(&self, name: &str, children: &'a Vec<Node>) -> Vec<BlockContent<'a>> {
+        let mut candidates = self.blocks(name);
+        candidates.push(BlockContent {
+            nodes: children,
+            template: self.template,
+            definitions: self.definitions,
+        });
+        return candidates;
+    }
+
+    /// The partials a parent template renders with, seeing the overrides of `scope`.
+    fn with_blocks(&self, scope: &'a BlockScope<'a>) -> Self {
+        return Self {
+            registry: self.registry,
+            template: self.template,
+            definitions: &[],
+            blocks: Some(scope),
+            supers: &[],
+        };
+    }
+
+    /// Partials do not see the block overrides of the template including them.
+    fn without_blocks(&self) -> Self {
+        return Self::new(self.registry);
+    }
+}
+
+/// The value of a partial argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    Identifier(String),
+    Literal(String),
 }
 
 pub trait Renderable {
@@ -79,7 +585,8 @@ pub trait Renderable {
         self,
         writable: &mut impl std::io::Write,
         context: &Value,
-        partials: Option<&HashMap<String, Vec<Node>>>,
+        partials: Option<&Partials>,
+        state: &mut RenderState,
     ) -> Result<(), RenderError>;
 }
 
@@ -88,10 +595,11 @@ impl Renderable for &Vec<Node> {
         self,
         writable: &mut impl std::io::Write,
         context: &Value,
-        partials: Option<&HashMap<String, Vec<Node>>>,
+        partials: Option<&Partials>,
+        state: &mut RenderState,
     ) -> Result<(), RenderError> {
         for node in self {
-            if let Err(error) = node.render(writable, context, partials) {
+            if let Err(error) = node.render(writable, context, partials, state) {
                 return Err(error);
             }
         }
@@ -104,82 +612,93 @@ impl Renderable for &Node {
         self,
         writable: &mut impl std::io::Write,
         context: &Value,
-        partials: Option<&HashMap<String, Vec<Node>>>,
+        partials: Option<&Partials>,
+        state: &mut RenderState,
     ) -> Result<(), RenderError> {
+        if let Err(error) = state.evaluate() {
+            return Err(error);
+        }
         match self {
             Node::Text(text) => {
-                writable.write(text.as_bytes()).unwrap();
+                if let Err(error) = state.write(writable, text.as_bytes()) {
+                    return Err(error);
+                }
             }
             Node::Variable {
                 identifier,
                 escaped,
-            } => match lookup(identifier.to_string(), context) {
-                Some(value) => {
+                dynamic,
+            } => match resolve_dynamic(identifier, *dynamic, context, state) {
+                Ok(value) => {
                     let string_value = value.to_string(context);
                     let escaped_value = match escaped {
-                        true => html_escape::encode_text(&string_value),
+                        true => state.escaper.escape(&string_value),
                         false => string_value.into(),
                     };
-                    writable.write(escaped_value.as_bytes()).unwrap();
+                    if let Err(error) = state.write(writable, escaped_value.as_bytes()) {
+                        return Err(error);
+                    }
                 }
-                None => return Err(RenderError::IdentifierDoesNotExist(identifier.into())),
+                Err(error) => return Err(error),
             },
             Node::Comment(_comment) => {}
             Node::Section {
                 identifier,
                 inverted,
+                dynamic,
                 children,
-            } => match lookup(identifier.to_string(), context) {
-                Some(value) => {
-                    if value.to_bool(context) || *inverted {
-                        match value {
-                            Value::Vec(vec) => {
-                                for value in vec {
-                                    if let Err(error) = children.render(writable, value, partials) {
-                                        return Err(error);
-                                    }
-                                }
-                            }
-                            _ => {
-                                for child in children {
-                                    if let Err(error) = child.render(writable, value, partials) {
-                                        return Err(error);
-                                    }
-                                }
-                            }
-                        }
-                    }
+            } => {
+                let key = dynamic_key(identifier, *dynamic, context, state)?;
+                let value = match &key {
+                    Some(key) => resolve_section(key, context, state)?,
+                    None => Cow::Borrowed(&MISSING),
+                };
+                state.enter_section(key.as_deref().unwrap_or(identifier));
+                let result = render_section(&value, *inverted, children, writable, context, partials, state);
+                state.exit_section();
+                if let Err(error) = result {
+                    return Err(error);
                 }
-                None => return Err(RenderError::IdentifierDoesNotExist(identifier.into())),
-            },
+            }
             Node::Implicit => {
-                writable
-                    .write(context.to_string(context).as_bytes())
-                    .unwrap();
+                if let Err(error) = state.write(writable, context.to_string(context).as_bytes()) {
+                    return Err(error);
+                }
             }
             Node::Partial {
                 identifier,
                 dynamic,
+                arguments,
             } => {
+                let frame = frame(arguments, context, state)?;
                 if let Some(partials) = partials {
-                    if *dynamic {
-                        if let Some(Value::String(dynamic_identifier)) = lookup(identifier.to_string(), context) {
-                            if let Some(partial) = partials.get(dynamic_identifier) {
-                                if let Err(error) = partial.render(writable, context, Some(partials)) {
+                    let definition = match dynamic {
+                        true => None,
+                        false => partials.definition(identifier),
+                    };
+                    if let Some(definition) = definition {
+                        let definition_partials = Partials {
+                            blocks: None,
+                            supers: &[],
+                            ..*partials
+                        };
+                        if let Err(error) = with_frame(frame, state, |state| {
+                            expand_nodes(identifier, definition, writable, context, &definition_partials, state)
+                        }) {
+                            return Err(error);
+                        }
+                    } else {
+                        let name = included_name(identifier, *dynamic, context, partials, state)?;
+                        match partials.get(&name) {
+                            Some(partial) => {
+                                if let Err(error) = with_frame(frame, state, |state| {
+                                    expand(&name, partial, writable, context, &partials.without_blocks(), state)
+                                }) {
                                     return Err(error);
                                 }
-                            } else {
-                                return Err(RenderError::PartialDoesNotExist(identifier.into()));
                             }
-                        } else {
-                            return Err(RenderError::IdentifierDoesNotExist(identifier.into()));
-                        }
-                    } else if let Some(partial) = partials.get(identifier) {
-                        if let Err(error) = partial.render(writable, context, Some(partials)) {
-                            return Err(error);
+                            None => return Err(missing_template(identifier, *dynamic, name)),
                         }
-                    } else {
-                        return Err(RenderError::PartialDoesNotExist(identifier.into()));
                     }
                 } else {
                     return Err(RenderError::PartialDoesNotExist(identifier.into()));
@@ -191,36 +710,15 @@ impl Renderable for &Node {
                 children,
             } => {
                 if let Some(partials) = partials {
-                    let mut new_partials = partials.clone();
-
-                    for node in children {
-                        if let Node::Block {
-                            identifier,
-                            children,
-                        } = node
-                        {
-                            new_partials.insert(identifier.into(), children.clone());
-                        }
-                    }
-
-                    if *dynamic {
-                        if let Some(Value::String(dynamic_identifier)) = lookup(identifier.to_string(), context) {
-                            if let Some(parent_partial) = partials.get(dynamic_identifier) {
-                                if let Err(error) = parent_partial.render(writable, context, Some(&new_partials)) {
-                                    return Err(error);
-                                }
-                            } else {
-                                return Err(RenderError::PartialDoesNotExist(identifier.into()));
+                    let scope = BlockScope::new(children, partials);
+                    let name = included_name(identifier, *dynamic, context, partials, state)?;
+                    match partials.get(&name) {
+                        Some(parent_partial) => {
+                            if let Err(error) = expand(&name, parent_partial, writable, context, &partials.with_blocks(&scope), state) {
+                                return Err(error);
                             }
-                        } else {
-                            return Err(RenderError::IdentifierDoesNotExist(identifier.into()));
-                        }
-                    } else if let Some(parent_partial) = partials.get(identifier) {
-                        if let Err(error) = parent_partial.render(writable, context, Some(&new_partials)) {
-                            return Err(error);
                         }
-                    } else {
-                        return Err(RenderError::PartialDoesNotExist(identifier.into()));
+                        None => return Err(missing_template(identifier, *dynamic, name)),
                     }
                 } else {
                     return Err(RenderError::PartialDoesNotExist(identifier.into()));
@@ -231,14 +729,17 @@ impl Renderable for &Node {
                 children,
             } => {
                 if let Some(partials) = partials {
-                    if let Some(partial) = partials.get(identifier) {
-                        if let Err(error) = partial.render(writable, context, Some(partials)) {
-                            return Err(error);
-                        }
-                    } else {
-                        if let Err(error) = children.render(writable, context, Some(partials)) {
-                            return Err(error);
-                        }
+                    let candidates = partials.candidates(identifier, children);
+                    if let Err(error) = render_super(&candidates, writable, context, partials, state) {
+                        return Err(error);
+                    }
+                }
+            }
+            Node::Definition { .. } | Node::Pragma(_) => {}
+            Node::Super => {
+                if let Some(partials) = partials {
+                    if let Err(error) = render_super(partials.supers, writable, context, partials, state) {
+                        return Err(error);
                     }
                 }
             }
@@ -247,33 +748,400 @@ impl Renderable for &Node {
     }
 }
 
-fn lookup(identifier: String, context: &Value) -> Option<&Value> {
-    return match context {
-        Value::Object(context) => {
-            let parts = identifier.split(".").collect::<Vec<&str>>();
-            let mut current_context = context;
-            let mut value = None;
+/// Renders the children of a section once for each item, or once for any other truthy value.
+fn render_section(
+    value: &Value,
+    inverted: bool,
+    children: &Vec<Node>,
+    writable: &mut impl std::io::Write,
+    context: &Value,
+    partials: Option<&Partials>,
+    state: &mut RenderState,
+) -> Result<(), RenderError> {
+    for value in section_contexts(value, inverted, context) {
+        if let Err(error) = children.render(writable, value, partials, state) {
+            return Err(error);
+        }
+    }
+    return Ok(());
+}
 
-            for i in 0..parts.len() {
-                let part = parts[i];
+/// The contexts the children of a section are rendered with, once for each item of a list.
+fn section_contexts<'v>(value: &'v Value, inverted: bool, context: &Value) -> Vec<&'v Value> {
+    if !value.to_bool(context) && !inverted {
+        return Vec::new();
+    }
+    return match value {
+        Value::Vec(vec) => vec.iter().collect(),
+        _ => vec![value],
+    };
+}
 
-                match current_context.get(part) {
-                    Some(new_context) => match new_context {
-                        Value::Object(new_context) => {
-                            current_context = new_context;
-                        }
-                        new_value => {
-                            value = Some(new_value);
+/// The arguments of a partial evaluated against the caller's context, or `None` when the
+/// partial has no arguments.
+fn frame(
+    arguments: &[(String, Argument)],
+    context: &Value,
+    state: &RenderState,
+) -> Result<Option<Frame>, RenderError> {
+    if arguments.is_empty() {
+        return Ok(None);
+    }
+    let mut frame = Frame {
+        depth: state.sections.len(),
+        arguments: HashMap::new(),
+    };
+    for (name, argument) in arguments {
+        let value = match argument {
+            Argument::Identifier(identifier) => match resolve(identifier, context, state) {
+                Ok(value) => value.into_owned(),
+                Err(error) => return Err(error),
+            },
+            Argument::Literal(literal) => Value::String(literal.clone()),
+        };
+        frame.arguments.insert(name.clone(), value);
+    }
+    return Ok(Some(frame));
+}
+
+/// Renders with the arguments of a partial layered over the context, if it has any.
+fn with_frame<'a>(
+    frame: Option<Frame>,
+    state: &mut RenderState<'a>,
+    render: impl FnOnce(&mut RenderState<'a>) -> Result<(), RenderError>,
+) -> Result<(), RenderError> {
+    let Some(frame) = frame else {
+        return render(state);
+    };
+    state.frames.push(frame);
+    let result = render(state);
+    state.frames.pop();
+    return result;
+}
+
+/// Renders the first of a block's candidate contents, leaving the rest to `{{@super}}`.
+fn render_super(
+    candidates: &[BlockContent],
+    writable: &mut impl std::io::Write,
+    context: &Value,
+    partials: &Partials,
+    state: &mut RenderState,
+) -> Result<(), RenderError> {
+    let Some((content, supers)) = candidates.split_first() else {
+        return Ok(());
+    };
+    let partials = Partials {
+        template: content.template,
+        definitions: content.definitions,
+        supers,
+        ..*partials
+    };
+    // Overrides render with the pragmas of the template they were written in
+    let previous = state.enter_pragmas(content.definitions)?;
+    let result = content.nodes.render(writable, context, Some(&partials), state);
+    state.exit_pragmas(previous);
+    return result;
+}
+
+/// Renders a partial or parent template, tracking it in the chain of active expansions.
+fn expand(
+    name: &str,
+    partial: &Template,
+    writable: &mut impl std::io::Write,
+    context: &Value,
+    partials: &Partials,
+    state: &mut RenderState,
+) -> Result<(), RenderError> {
+    return expand_with(name, partial, partials, state, |nodes, partials, state| {
+        nodes.render(writable, context, Some(partials), state)
+    });
+}
+
+/// Expands a partial or parent template, passing its nodes to `render` with its pragmas
+/// applied while it is tracked in the chain of active expansions.
+fn expand_with<T>(
+    name: &str,
+    partial: &Template,
+    partials: &Partials,
+    state: &mut RenderState,
+    render: impl FnOnce(&Vec<Node>, &Partials, &mut RenderState) -> Result<T, RenderError>,
+) -> Result<T, RenderError> {
+    if !state.sandbox.allows_partial(name) {
+        return Err(RenderError::PartialNotAllowed(name.into()));
+    }
+    let partial = partial.nodes()?;
+    let partials = partials.with_template(name, partial);
+    let previous = state.enter_pragmas(partial)?;
+    let result = match state.enter(name) {
+        Ok(()) => {
+            let result = render(partial, &partials, state);
+            state.exit();
+            result
+        }
+        Err(error) => Err(error),
+    };
+    state.exit_pragmas(previous);
+    return result;
+}
+
+/// The name of the template a partial or parent tag includes. Dynamic names are looked up in
+/// the context and checked against the dynamic name policy.
+fn included_name(
+    identifier: &str,
+    dynamic: bool,
+    context: &Value,
+    partials: &Partials,
+    state: &RenderState,
+) -> Result<String, RenderError> {
+    if !dynamic {
+        return partials.template_name(identifier);
+    }
+    return match resolve(identifier, context, state)?.as_ref() {
+        Value::String(name) => {
+            let name = partials.template_name(name)?;
+            state.dynamic_name(identifier, &name)
+        }
+        _ => Err(RenderError::IdentifierDoesNotExist(identifier.into())),
+    };
+}
+
+/// Dynamic tags report the identifier the missing template was named by.
+fn missing_template(identifier: &str, dynamic: bool, name: String) -> RenderError {
+    return match dynamic {
+        true => RenderError::PartialDoesNotExist(identifier.into()),
+        false => RenderError::PartialDoesNotExist(name),
+    };
+}
+
+/// Renders only `fragment` out of `nodes`, following parent tags into the templates they
+/// expand, blocks into the content they resolve to and sections into each context they render
+/// with. Returns whether it was rendered.
+pub(crate) fn render_fragment(
+    fragment: &Fragment,
+    nodes: &[Node],
+    writable: &mut impl std::io::Write,
+    context: &Value,
+    partials: &Partials,
+    state: &mut RenderState,
+) -> Result<bool, RenderError> {
+    for node in nodes {
+        match node {
+            Node::Section { identifier, .. } if *fragment == Fragment::Section(identifier.clone()) => {
+                node.render(writable, context, Some(partials), state)?;
+                return Ok(true);
+            }
+            Node::Section {
+                identifier,
+                inverted,
+                dynamic,
+                children,
+            } => {
+                let key = dynamic_key(identifier, *dynamic, context, state)?;
+                let value = match &key {
+                    Some(key) => resolve_section(key, context, state)?,
+                    None => Cow::Borrowed(&MISSING),
+                };
+                state.enter_section(key.as_deref().unwrap_or(identifier));
+                let mut found = false;
+                let mut result = Ok(());
+                for value in section_contexts(&value, *inverted, context) {
+                    match render_fragment(fragment, children, writable, value, partials, state) {
+                        Ok(rendered) => found |= rendered,
+                        Err(error) => {
+                            result = Err(error);
+                            break;
                         }
-                    },
-                    None => {
-                        return None;
                     }
                 }
+                state.exit_section();
+                if let Err(error) = result {
+                    return Err(error);
+                }
+                if found {
+                    return Ok(true);
+                }
+            }
+            Node::Block {
+                identifier,
+                children,
+            } => {
+                let candidates = partials.candidates(identifier, children);
+                if *fragment == Fragment::Block(identifier.clone()) {
+                    render_super(&candidates, writable, context, partials, state)?;
+                    return Ok(true);
+                }
+
+                let (content, supers) = candidates.split_first().unwrap();
+                let content_partials = Partials {
+                    template: content.template,
+                    definitions: content.definitions,
+                    supers,
+                    ..*partials
+                };
+                let previous = state.enter_pragmas(content.definitions)?;
+                let found = render_fragment(fragment, content.nodes, writable, context, &content_partials, state);
+                state.exit_pragmas(previous);
+                if found? {
+                    return Ok(true);
+                }
+            }
+            Node::Parent {
+                identifier,
+                dynamic,
+                children,
+            } => {
+                let scope = BlockScope::new(children, partials);
+                let name = included_name(identifier, *dynamic, context, partials, state)?;
+                let Some(parent) = partials.get(&name) else {
+                    return Err(missing_template(identifier, *dynamic, name));
+                };
+                let found = expand_with(&name, parent, &partials.with_blocks(&scope), state, |nodes, partials, state| {
+                    render_fragment(fragment, nodes, writable, context, partials, state)
+                })?;
+                if found {
+                    return Ok(true);
+                }
             }
+            _ => {}
+        }
+    }
+    return Ok(false);
+}
+
+/// Renders the nodes of a template or inline definition, `name` only labels the expansion in
+/// the chain so cycles can be reported.
+fn expand_nodes(
+    name: &str,
+    nodes: &Vec<Node>,
+    writable: &mut impl std::io::Write,
+    context: &Value,
+    partials: &Partials,
+    state: &mut RenderState,
+) -> Result<(), RenderError> {
+    if let Err(error) = state.enter(name) {
+        return Err(error);
+    }
+    let result = nodes.render(writable, context, Some(partials), state);
+    state.exit();
+    return result;
+}
+
+/// Looks up an identifier the sandbox allows the template to reach. Under `{{%ANCHORED-DOT}}`
+/// a leading `.` anchors the lookup to the current context, skipping partial arguments.
+fn resolve<'a>(
+    identifier: &str,
+    context: &'a Value,
+    state: &RenderState,
+) -> Result<Cow<'a, Value>, RenderError> {
+    return resolve_allowed(identifier, false, context, state);
+}
+
+/// Looks up the identifier of a section, which the sandbox allows when an allowed key is
+/// nested beneath it.
+fn resolve_section<'a>(
+    identifier: &str,
+    context: &'a Value,
+    state: &RenderState,
+) -> Result<Cow<'a, Value>, RenderError> {
+    let value = resolve_allowed(identifier, true, context, state)?;
+    // The length of an empty list renders as `0` but is falsy
+    if let Some(list) = identifier.strip_suffix(".length") {
+        if state.length_property {
+            if let Value::Vec(items) = resolve_allowed(list, true, context, state)?.as_ref() {
+                if items.is_empty() {
+                    return Ok(Cow::Borrowed(&MISSING));
+                }
+            }
+        }
+    }
+    return Ok(value);
+}
 
-            return value;
+fn resolve_allowed<'a>(
+    identifier: &str,
+    section: bool,
+    context: &'a Value,
+    state: &RenderState,
+) -> Result<Cow<'a, Value>, RenderError> {
+    if state.iterator.as_deref() == Some(identifier) {
+        return Ok(Cow::Borrowed(context));
+    }
+    let anchored = state.anchored_dot && identifier.starts_with('.');
+    let path = match anchored {
+        true => &identifier[1..],
+        false => identifier,
+    };
+    // Arguments were checked against the sandbox when they were evaluated
+    if !anchored {
+        if let Some(value) = state.argument(path) {
+            return Ok(Cow::Owned(value));
         }
-        _ => Some(context),
+    }
+    if let Err(error) = state.allow_identifier(identifier.strip_prefix('.').unwrap_or(identifier), section) {
+        return Err(error);
+    }
+    return match lookup(path, context, state.length_property) {
+        Some(value) => Ok(value),
+        None if !state.strict => Ok(Cow::Borrowed(&MISSING)),
+        None => Err(RenderError::IdentifierDoesNotExist(identifier.into())),
     };
 }
+
+/// Looks up an identifier, or for a dynamic identifier looks up the key named by its value.
+fn resolve_dynamic<'a>(
+    identifier: &str,
+    dynamic: bool,
+    context: &'a Value,
+    state: &RenderState,
+) -> Result<Cow<'a, Value>, RenderError> {
+    return match dynamic_key(identifier, dynamic, context, state)? {
+        Some(key) => resolve(&key, context, state),
+        None => Ok(Cow::Borrowed(&MISSING)),
+    };
+}
+
+/// The key an identifier looks up, for a dynamic identifier the key named by its value, or
+/// `None` when a lenient render finds no name.
+fn dynamic_key(
+    identifier: &str,
+    dynamic: bool,
+    context: &Value,
+    state: &RenderState,
+) -> Result<Option<String>, RenderError> {
+    if !dynamic {
+        return Ok(Some(identifier.into()));
+    }
+    let value = resolve(identifier, context, state)?;
+    return match value.as_ref() {
+        Value::String(name) => Ok(Some(name.clone())),
+        Value::None if !state.strict => Ok(None),
+        _ => Err(RenderError::IdentifierDoesNotExist(identifier.into())),
+    };
+}
+
+/// Walks a dotted name through objects by key and lists by index. `length` on a list is its
+/// number of items when enabled and the list has no such index.
+fn lookup<'a>(identifier: &str, context: &'a Value, length_property: bool) -> Option<Cow<'a, Value>> {
+    if !matches!(context, Value::Object(_) | Value::Vec(_)) {
+        return Some(Cow::Borrowed(context));
+    }
+
+    let parts = identifier.split('.').collect::<Vec<&str>>();
+    let mut value = context;
+
+    for (i, part) in parts.iter().enumerate() {
+        value = match value {
+            Value::Object(object) => object.get(*part)?,
+            Value::Vec(vec) => match part.parse::<usize>() {
+                Ok(index) => vec.get(index)?,
+                Err(_) if length_property && *part == "length" && i == parts.len() - 1 => {
+                    return Some(Cow::Owned(Value::String(vec.len().to_string())));
+                }
+                Err(_) => return None,
+            },
+            _ => return None,
+        };
+    }
+
+    return Some(Cow::Borrowed(value));
+}
@@ -4,7 +4,11 @@ use anyhow::Result;
 use crossbeam_channel::Receiver;
 use thiserror::Error;
 
-use crate::{lexer::Token, node::Node};
+use crate::{
+    lexer::Token,
+    node::{Argument, Node},
+    pragma::Pragma,
+};
 
 #[derive(Error, Debug)]
 pub enum ParserError {
@@ -14,19 +18,42 @@ pub enum ParserError {
         column: usize,
         message: String,
     },
-    #[error("unexpected token: {0:#?}")]
-    UnexpectedToken(Token),
+    #[error("{line}:{column} unexpected token: {token:#?}")]
+    UnexpectedToken {
+        line: usize,
+        column: usize,
+        token: Token,
+    },
     #[error("expected token: {0:#?} got nothing")]
     ExpectedToken(Token),
     #[error("expected token: {0:#?} got {1:#?}")]
     ExpectedTokenGot(Token, Token),
-    #[error("unclosed section: {0}")]
-    UnclosedSection(String),
+    #[error("{line}:{column} unclosed section: {identifier}")]
+    UnclosedSection {
+        line: usize,
+        column: usize,
+        identifier: String,
+    },
+}
+
+impl ParserError {
+    /// The line and column in the template source the error was raised at, if known.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        return match self {
+            ParserError::SyntaxError { line, column, .. }
+            | ParserError::UnexpectedToken { line, column, .. }
+            | ParserError::UnclosedSection { line, column, .. } => Some((*line, *column)),
+            _ => None,
+        };
+    }
 }
 
 struct Parser {
+    /// `None` for the parsers of section contents, which read from `buffer` alone.
     tokens: Option<Receiver<Token>>,
     buffer: VecDeque<Token>,
+    line: usize,
+    column: usize,
 }
 
 impl Parser {
@@ -34,6 +61,8 @@ impl Parser {
         return Self {
             tokens,
             buffer: VecDeque::new(),
+            line: 0,
+            column: 0,
         };
     }
 
@@ -66,7 +95,9 @@ impl Parser {
                 Token::CloseDelimiter => {}
                 Token::EOF => {}
                 Token::Text(text) => nodes.push(Node::Text(text)),
-                Token::OpenDelimiter => {
+                Token::OpenDelimiter { line, column } => {
+                    self.line = line;
+                    self.column = column;
                     if let Some(token) = self.next() {
                         match token {
                             Token::Error {
@@ -81,31 +112,47 @@ impl Parser {
                                 })
                             }
                             Token::Comment(comment) => nodes.push(Node::Comment(comment)),
+                            Token::Verbatim(text) => nodes.push(Node::Text(text)),
                             Token::SetDelimiter => {}
                             Token::Partial => {
                                 if let Some(token) = self.next() {
                                     match token {
                                         Token::Identifier(identifier) => {
+                                            let arguments = self.arguments()?;
                                             nodes.push(Node::Partial {
                                                 identifier,
                                                 dynamic: false,
+                                                arguments,
                                             });
                                         }
-                                        Token::Dynamic => {
-                                            if let Some(Token::Identifier(identifier)) = self.next()
-                                            {
+                                        Token::Dynamic => match self.identifier() {
+                                            Ok(identifier) => {
+                                                let arguments = self.arguments()?;
                                                 nodes.push(Node::Partial {
                                                     identifier,
                                                     dynamic: true,
+                                                    arguments,
                                                 });
                                             }
+                                            Err(error) => return Err(error),
+                                        },
+                                        Token::Error {
+                                            line,
+                                            column,
+                                            message,
+                                        } => {
+                                            return Err(ParserError::SyntaxError {
+                                                line,
+                                                column,
+                                                message,
+                                            })
                                         }
-                                        _ => return Err(ParserError::UnexpectedToken(token)),
+                                        _ => return Err(self.unexpected_token(token)),
                                     }
                                 }
                             }
-                            Token::Section => {
-                                if let Some(Token::Identifier(identifier)) = self.next() {
+                            Token::Section => match self.dynamic_identifier() {
+                                Ok((identifier, dynamic)) => {
                                     if let Some(tokens) = self.section_tokens(&identifier) {
                                         let mut sub_parser = Parser::new(None);
                                         sub_parser.buffer = tokens.into();
@@ -115,18 +162,20 @@ impl Parser {
                                                 nodes.push(Node::Section {
                                                     identifier,
                                                     inverted: false,
+                                                    dynamic,
                                                     children,
                                                 });
                                             }
                                             Err(error) => return Err(error),
                                         }
                                     } else {
-                                        return Err(ParserError::UnclosedSection(identifier));
+                                        return Err(self.unclosed_section(identifier));
                                     }
                                 }
-                            }
-                            Token::InvertedSection => {
-                                if let Some(Token::Identifier(identifier)) = self.next() {
+                                Err(error) => return Err(error),
+                            },
+                            Token::InvertedSection => match self.dynamic_identifier() {
+                                Ok((identifier, dynamic)) => {
                                     if let Some(tokens) = self.section_tokens(&identifier) {
                                         let mut sub_parser = Parser::new(None);
                                         sub_parser.buffer = tokens.into();
@@ -136,18 +185,20 @@ impl Parser {
                                                 nodes.push(Node::Section {
                                                     identifier,
                                                     inverted: true,
+                                                    dynamic,
                                                     children,
                                                 });
                                             }
                                             Err(error) => return Err(error),
                                         }
                                     } else {
-                                        return Err(ParserError::UnclosedSection(identifier));
+                                        return Err(self.unclosed_section(identifier));
                                     }
                                 }
-                            }
-                            Token::Block => {
-                                if let Some(Token::Identifier(identifier)) = self.next() {
+                                Err(error) => return Err(error),
+                            },
+                            Token::Block => match self.identifier() {
+                                Ok(identifier) => {
                                     if let Some(tokens) = self.section_tokens(&identifier) {
                                         let mut sub_parser = Parser::new(None);
                                         sub_parser.buffer = tokens.into();
@@ -162,10 +213,39 @@ impl Parser {
                                             Err(error) => return Err(error),
                                         }
                                     } else {
-                                        return Err(ParserError::UnclosedSection(identifier));
+                                        return Err(self.unclosed_section(identifier));
                                     }
                                 }
-                            }
+                                Err(error) => return Err(error),
+                            },
+                            Token::Define => match self.identifier() {
+                                Ok(identifier) => {
+                                    // Only definitions at the top level are ever looked up
+                                    if self.tokens.is_none() {
+                                        return Err(self.syntax_error(format!(
+                                            "definition: '{}' must be at the top level of the template",
+                                            identifier
+                                        )));
+                                    }
+                                    if let Some(tokens) = self.section_tokens(&identifier) {
+                                        let mut sub_parser = Parser::new(None);
+                                        sub_parser.buffer = tokens.into();
+
+                                        match sub_parser.parse() {
+                                            Ok(children) => {
+                                                nodes.push(Node::Definition {
+                                                    identifier,
+                                                    children,
+                                                });
+                                            }
+                                            Err(error) => return Err(error),
+                                        }
+                                    } else {
+                                        return Err(self.unclosed_section(identifier));
+                                    }
+                                }
+                                Err(error) => return Err(error),
+                            },
                             Token::Parent => {
                                 if let Some(token) = self.next() {
                                     match token {
@@ -185,14 +265,11 @@ impl Parser {
                                                     Err(error) => return Err(error),
                                                 }
                                             } else {
-                                                return Err(ParserError::UnclosedSection(
-                                                    identifier,
-                                                ));
+                                                return Err(self.unclosed_section(identifier));
                                             }
                                         }
-                                        Token::Dynamic => {
-                                            if let Some(Token::Identifier(identifier)) = self.next()
-                                            {
+                                        Token::Dynamic => match self.identifier() {
+                                            Ok(identifier) => {
                                                 if let Some(tokens) =
                                                     self.section_tokens(&identifier)
                                                 {
@@ -210,42 +287,198 @@ impl Parser {
                                                         Err(error) => return Err(error),
                                                     }
                                                 } else {
-                                                    return Err(ParserError::UnclosedSection(
-                                                        identifier,
-                                                    ));
+                                                    return Err(self.unclosed_section(identifier));
                                                 }
                                             }
+                                            Err(error) => return Err(error),
+                                        },
+                                        Token::Error {
+                                            line,
+                                            column,
+                                            message,
+                                        } => {
+                                            return Err(ParserError::SyntaxError {
+                                                line,
+                                                column,
+                                                message,
+                                            })
                                         }
-                                        _ => return Err(ParserError::UnexpectedToken(token)),
+                                        _ => return Err(self.unexpected_token(token)),
                                     }
                                 }
                             }
+                            Token::Identifier(identifier) if identifier == "." => {
+                                nodes.push(Node::Implicit)
+                            }
                             Token::Identifier(identifier) => {
                                 nodes.push(Node::Variable {
                                     identifier,
                                     escaped: true,
+                                    dynamic: false,
                                 });
                             }
-                            Token::Raw => {
-                                if let Some(Token::Identifier(identifier)) = self.next() {
-                                    nodes.push(Node::Variable {
-                                        identifier,
-                                        escaped: false,
-                                    })
-                                }
-                            }
+                            Token::Dynamic => match self.identifier() {
+                                Ok(identifier) => nodes.push(Node::Variable {
+                                    identifier,
+                                    escaped: true,
+                                    dynamic: true,
+                                }),
+                                Err(error) => return Err(error),
+                            },
+                            Token::Raw => match self.dynamic_identifier() {
+                                Ok((identifier, dynamic)) => nodes.push(Node::Variable {
+                                    identifier,
+                                    escaped: false,
+                                    dynamic,
+                                }),
+                                Err(error) => return Err(error),
+                            },
                             Token::Implicit => nodes.push(Node::Implicit),
-                            _ => return Err(ParserError::UnexpectedToken(token)),
+                            Token::Pragma => match self.identifier() {
+                                Ok(name) => {
+                                    let arguments = self.arguments()?;
+                                    if self.tokens.is_none() {
+                                        return Err(self.syntax_error(format!(
+                                            "pragma: '{}' must be at the top level of the template",
+                                            name
+                                        )));
+                                    }
+                                    match Pragma::parse(&name, &arguments) {
+                                        Ok(pragma) => nodes.push(Node::Pragma(pragma)),
+                                        Err(error) => {
+                                            return Err(self.syntax_error(error.to_string()))
+                                        }
+                                    }
+                                }
+                                Err(error) => return Err(error),
+                            },
+                            Token::Special => match self.next() {
+                                Some(Token::Identifier(identifier)) if identifier == "super" => {
+                                    nodes.push(Node::Super)
+                                }
+                                Some(token) => return Err(self.unexpected_token(token)),
+                                None => {}
+                            },
+                            _ => return Err(self.unexpected_token(token)),
                         }
                     }
                 }
-                _ => return Err(ParserError::UnexpectedToken(token)),
+                _ => return Err(self.unexpected_token(token)),
             }
         }
 
         return Ok(nodes);
     }
 
+    /// The `name=value` arguments of a partial tag, up to and including its close delimiter.
+    fn arguments(&mut self) -> Result<Vec<(String, Argument)>, ParserError> {
+        let mut arguments: Vec<(String, Argument)> = Vec::new();
+
+        while let Some(token) = self.next() {
+            match token {
+                Token::CloseDelimiter => break,
+                Token::Error {
+                    line,
+                    column,
+                    message,
+                } => {
+                    return Err(ParserError::SyntaxError {
+                        line,
+                        column,
+                        message,
+                    })
+                }
+                Token::Argument(name) => {
+                    if arguments.iter().any(|(other, _)| other == &name) {
+                        return Err(self.syntax_error(format!("duplicate argument: '{}'", name)));
+                    }
+                    let value = match self.next() {
+                        Some(Token::Identifier(identifier)) => Argument::Identifier(identifier),
+                        Some(Token::Literal(literal)) => Argument::Literal(literal),
+                        Some(Token::Error {
+                            line,
+                            column,
+                            message,
+                        }) => {
+                            return Err(ParserError::SyntaxError {
+                                line,
+                                column,
+                                message,
+                            })
+                        }
+                        Some(token) => return Err(self.unexpected_token(token)),
+                        None => return Err(ParserError::ExpectedToken(Token::CloseDelimiter)),
+                    };
+                    arguments.push((name, value));
+                }
+                _ => return Err(self.unexpected_token(token)),
+            }
+        }
+
+        return Ok(arguments);
+    }
+
+    /// The identifier following a sigil, or the error the lexer reported in its place.
+    fn identifier(&mut self) -> Result<String, ParserError> {
+        return match self.next() {
+            Some(Token::Identifier(identifier)) => Ok(identifier),
+            Some(Token::Error {
+                line,
+                column,
+                message,
+            }) => Err(ParserError::SyntaxError {
+                line,
+                column,
+                message,
+            }),
+            Some(token) => Err(self.unexpected_token(token)),
+            None => Err(ParserError::ExpectedToken(Token::Identifier(String::new()))),
+        };
+    }
+
+    /// An identifier that may be marked dynamic with `*`, along with whether it was.
+    fn dynamic_identifier(&mut self) -> Result<(String, bool), ParserError> {
+        return match self.next() {
+            Some(Token::Dynamic) => match self.identifier() {
+                Ok(identifier) => Ok((identifier, true)),
+                Err(error) => Err(error),
+            },
+            Some(token) => {
+                // Put the token back to be read as a plain identifier
+                self.buffer.push_front(token);
+                match self.identifier() {
+                    Ok(identifier) => Ok((identifier, false)),
+                    Err(error) => Err(error),
+                }
+            }
+            None => Err(ParserError::ExpectedToken(Token::Identifier(String::new()))),
+        };
+    }
+
+    fn syntax_error(&self, message: String) -> ParserError {
+        return ParserError::SyntaxError {
+            line: self.line,
+            column: self.column,
+            message,
+        };
+    }
+
+    fn unexpected_token(&self, token: Token) -> ParserError {
+        return ParserError::UnexpectedToken {
+            line: self.line,
+            column: self.column,
+            token,
+        };
+    }
+
+    fn unclosed_section(&self, identifier: String) -> ParserError {
+        return ParserError::UnclosedSection {
+            line: self.line,
+            column: self.column,
+            identifier,
+        };
+    }
+
     fn section_tokens(&mut self, identifier: &String) -> Option<Vec<Token>> {
         let mut tokens = Vec::new();
 
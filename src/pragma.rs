@@ -0,0 +1,144 @@
+use thiserror::Error;
+
+use crate::node::{Argument, Node};
+
+#[derive(Error, Debug)]
+pub enum PragmaError {
+    #[error("unknown pragma: '{0}'")]
+    Unknown(String),
+    #[error("pragma: '{pragma}' requires argument: '{argument}'")]
+    MissingArgument { pragma: String, argument: String },
+    #[error("pragma: '{pragma}' does not take argument: '{argument}'")]
+    UnexpectedArgument { pragma: String, argument: String },
+    #[error("pragma: '{pragma}' argument: '{argument}' cannot be: '{value}'")]
+    InvalidArgument {
+        pragma: String,
+        argument: String,
+        value: String,
+    },
+}
+
+/// How a template declaring `{{%ESCAPE mode=...}}` escapes the values of `{{name}}` tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeMode {
+    Html,
+    None,
+}
+
+/// A `{{%NAME key=value}}` tag, changing how the template it appears in renders.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pragma {
+    /// `{{%IMPLICIT-ITERATOR iterator=item}}` lets `{{item}}` be written for `{{.}}`.
+    ImplicitIterator(String),
+    /// `{{%ANCHORED-DOT}}` anchors `{{.name}}` to the current context, so it never resolves to
+    /// an argument of the partial being rendered.
+    AnchoredDot,
+    /// `{{%STRICT}}` makes missing identifiers errors.
+    Strict,
+    /// `{{%LENIENT}}` renders missing identifiers as empty.
+    Lenient,
+    /// `{{%ESCAPE mode=none}}` or `{{%ESCAPE mode=html}}`. `html` keeps a custom escaper the
+    /// templates are rendered with, and only falls back to HTML escaping when it is disabled.
+    Escape(EscapeMode),
+}
+
+/// A pragma a template may declare, with the argument it requires if any.
+#[derive(Debug, Clone, Copy)]
+pub struct PragmaDefinition {
+    pub name: &'static str,
+    pub argument: Option<&'static str>,
+    /// Builds the pragma from the value of its argument, or `None` if the value is invalid.
+    build: fn(Option<&str>) -> Option<Pragma>,
+}
+
+/// Every pragma a template may declare.
+pub const PRAGMAS: &[PragmaDefinition] = &[
+    PragmaDefinition {
+        name: "IMPLICIT-ITERATOR",
+        argument: Some("iterator"),
+        build: |value| value.map(|iterator| Pragma::ImplicitIterator(iterator.into())),
+    },
+    PragmaDefinition {
+        name: "ANCHORED-DOT",
+        argument: None,
+        build: |_| Some(Pragma::AnchoredDot),
+    },
+    PragmaDefinition {
+        name: "STRICT",
+        argument: None,
+        build: |_| Some(Pragma::Strict),
+    },
+    PragmaDefinition {
+        name: "LENIENT",
+        argument: None,
+        build: |_| Some(Pragma::Lenient),
+    },
+    PragmaDefinition {
+        name: "ESCAPE",
+        argument: Some("mode"),
+        build: |value| match value {
+            Some("html") => Some(Pragma::Escape(EscapeMode::Html)),
+            Some("none") => Some(Pragma::Escape(EscapeMode::None)),
+            _ => None,
+        },
+    },
+];
+
+impl Pragma {
+    /// The name the pragma is declared with.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Pragma::ImplicitIterator(_) => "IMPLICIT-ITERATOR",
+            Pragma::AnchoredDot => "ANCHORED-DOT",
+            Pragma::Strict => "STRICT",
+            Pragma::Lenient => "LENIENT",
+            Pragma::Escape(_) => "ESCAPE",
+        };
+    }
+
+    /// Looks a pragma up in [`PRAGMAS`] and checks its arguments.
+    pub fn parse(name: &str, arguments: &[(String, Argument)]) -> Result<Self, PragmaError> {
+        let Some(definition) = PRAGMAS.iter().find(|definition| definition.name == name) else {
+            return Err(PragmaError::Unknown(name.into()));
+        };
+
+        for (argument, _) in arguments {
+            if Some(argument.as_str()) != definition.argument {
+                return Err(PragmaError::UnexpectedArgument {
+                    pragma: name.into(),
+                    argument: argument.clone(),
+                });
+            }
+        }
+
+        let value = match (definition.argument, arguments.first()) {
+            (None, _) => None,
+            (Some(_), Some((_, Argument::Identifier(value) | Argument::Literal(value)))) => {
+                Some(value.as_str())
+            }
+            (Some(argument), None) => {
+                return Err(PragmaError::MissingArgument {
+                    pragma: name.into(),
+                    argument: argument.into(),
+                })
+            }
+        };
+
+        return match (definition.build)(value) {
+            Some(pragma) => Ok(pragma),
+            None => Err(PragmaError::InvalidArgument {
+                pragma: name.into(),
+                argument: definition.argument.unwrap_or_default().into(),
+                value: value.unwrap_or_default().into(),
+            }),
+        };
+    }
+}
+
+/// The pragmas declared at the top level of a template.
+pub fn pragmas(nodes: &[Node]) -> impl Iterator<Item = &Pragma> {
+    return nodes.iter().filter_map(|node| match node {
+        Node::Pragma(pragma) => Some(pragma),
+        _ => None,
+    });
+}
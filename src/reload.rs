@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock, Weak},
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Error, Result};
+use glob::glob;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{
+    loader::Template,
+    naming::{Naming, NamingError},
+    node::RenderError,
+    parse_file,
+    parser::ParserError,
+    Rustache,
+};
+
+#[derive(Error, Debug)]
+pub enum ReloadError {
+    #[error("{}:{error}", .path.display())]
+    Parse { path: PathBuf, error: ParserError },
+    #[error("{}: {error}", .path.display())]
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error(transparent)]
+    Naming(#[from] NamingError),
+    #[error(transparent)]
+    Glob(#[from] glob::PatternError),
+}
+
+/// A [`Rustache`] that picks up changes to its template files. Each render uses a snapshot of
+/// the registry, so reloads never affect renders that are already in flight.
+#[derive(Debug)]
+pub struct Reloading {
+    glob_pattern: String,
+    naming: Naming,
+    current: RwLock<Arc<Rustache>>,
+    modified: Mutex<HashMap<PathBuf, SystemTime>>,
+}
+
+impl Reloading {
+    pub fn new(directory: &str, glob_pattern: &str) -> Result<Self, Error> {
+        return Self::with_naming(directory, glob_pattern, Naming::default());
+    }
+
+    pub fn with_naming(directory: &str, glob_pattern: &str, naming: Naming) -> Result<Self, Error> {
+        // Record modification times before loading so changes made while loading are not missed
+        let modified = modification_times(directory, glob_pattern)?;
+        let rustache = Rustache::with_naming(directory, glob_pattern, &naming)?;
+
+        return Ok(Self {
+            glob_pattern: glob_pattern.into(),
+            naming,
+            current: RwLock::new(Arc::new(rustache)),
+            modified: Mutex::new(modified),
+        });
+    }
+
+    /// The registry as of the last successful reload.
+    pub fn snapshot(&self) -> Arc<Rustache> {
+        return self.current.read().unwrap().clone();
+    }
+
+    pub fn render<T>(
+        &self,
+        name: &str,
+        writable: &mut impl std::io::Write,
+        context: &T,
+    ) -> Result<(), RenderError>
+    where
+        T: Serialize,
+    {
+        return self.snapshot().render(name, writable, context);
+    }
+
+    /// Re-parses templates that were added or modified since the last reload, drops templates
+    /// whose files were removed and swaps the updated registry in. Templates that fail to parse
+    /// keep their previous version and are reported as errors.
+    pub fn reload(&self) -> Vec<ReloadError> {
+        let mut modified = self.modified.lock().unwrap();
+        let mut errors = Vec::new();
+        let current = self.snapshot();
+
+        let times = match modification_times(&current.directory, &self.glob_pattern) {
+            Ok(times) => times,
+            Err(error) => {
+                errors.push(ReloadError::from(error));
+                return errors;
+            }
+        };
+
+        // A path has more than one name when it is aliased
+        let mut names: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        match self.naming.names(&current.directory, times.keys().cloned()) {
+            Ok(named_paths) => {
+                for (name, path) in named_paths {
+                    names.entry(path).or_default().push(name);
+                }
+            }
+            Err(error) => {
+                errors.push(ReloadError::from(error));
+                return errors;
+            }
+        }
+
+        let mut changed = Vec::new();
+        let mut removed = Vec::new();
+
+        for (path, time) in &times {
+            if modified.get(path) != Some(time) {
+                changed.push(path.clone());
+            }
+        }
+
+        for path in modified.keys() {
+            if !times.contains_key(path) {
+                removed.push(path.clone());
+            }
+        }
+
+        if changed.is_empty() && removed.is_empty() {
+            return errors;
+        }
+
+        let mut rustache = current.as_ref().clone();
+
+        for path in removed {
+            if let Some(name) = self.naming.name(&current.directory, &path) {
+                rustache.partials.remove(&name);
+            }
+            for (alias, name) in &self.naming.aliases {
+                if !rustache.partials.contains_key(name) {
+                    rustache.partials.remove(alias);
+                }
+            }
+            modified.remove(&path);
+        }
+
+        for path in changed {
+            let Some(path_names) = names.get(&path) else {
+                continue;
+            };
+
+            let nodes = match File::open(&path) {
+                Ok(file) => parse_file(file, &current.syntax),
+                Err(error) => {
+                    errors.push(ReloadError::Io { path, error });
+                    continue;
+                }
+            };
+
+            match nodes {
+                Ok(nodes) => {
+                    for name in path_names {
+                        let template = Template::parsed(name, nodes.clone())
+                            .with_origin(path.display().to_string());
+                        rustache.partials.insert(name.clone(), template);
+                    }
+                    modified.insert(path.clone(), times[&path]);
+                }
+                Err(error) => {
+                    // Remember the broken version so it is only reported once
+                    modified.insert(path.clone(), times[&path]);
+                    errors.push(ReloadError::Parse { path, error });
+                }
+            }
+        }
+
+        *self.current.write().unwrap() = Arc::new(rustache);
+
+        return errors;
+    }
+
+    /// Polls for changes on a background thread every `interval`, passing any errors to
+    /// `on_error`. The thread stops once the last reference to `reloading` is dropped.
+    pub fn watch(
+        reloading: &Arc<Self>,
+        interval: Duration,
+        on_error: impl Fn(&ReloadError) + Send + 'static,
+    ) -> JoinHandle<()> {
+        let reloading: Weak<Self> = Arc::downgrade(reloading);
+
+        return std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+
+            let Some(reloading) = reloading.upgrade() else {
+                break;
+            };
+
+            for error in reloading.reload() {
+                on_error(&error);
+            }
+        });
+    }
+}
+
+fn modification_times(
+    directory: &str,
+    glob_pattern: &str,
+) -> Result<HashMap<PathBuf, SystemTime>, glob::PatternError> {
+    let mut times = HashMap::new();
+
+    for entry in glob(&[directory, "/", glob_pattern].concat())? {
+        if let Ok(path) = entry {
+            if let Ok(time) = path.metadata().and_then(|metadata| metadata.modified()) {
+                times.insert(path, time);
+            }
+        }
+    }
+
+    return Ok(times);
+}
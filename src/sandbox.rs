@@ -0,0 +1,216 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// A cheaply cloneable flag used to abort a render from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.0.load(Ordering::Relaxed);
+    }
+}
+
+/// Resource limits and allow-lists applied while rendering untrusted templates. Every limit is
+/// disabled by default.
+#[derive(Debug, Clone, Default)]
+pub struct Sandbox {
+    pub max_output_bytes: Option<usize>,
+    pub max_evaluations: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub cancellation: Option<CancellationToken>,
+    pub allowed_keys: Option<HashSet<String>>,
+    pub allowed_partials: Option<HashSet<String>>,
+    pub allowed_pragmas: Option<HashSet<String>>,
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// Caps the number of bytes written to the output.
+    pub fn max_output_bytes(mut self, bytes: usize) -> Self {
+        self.max_output_bytes = Some(bytes);
+        return self;
+    }
+
+    /// Caps the number of nodes evaluated, including every iteration of a section.
+    pub fn max_evaluations(mut self, evaluations: usize) -> Self {
+        self.max_evaluations = Some(evaluations);
+        return self;
+    }
+
+    /// Caps the number of nested partials and parents. The depth limit of the templates still
+    /// applies when it is lower.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        return self;
+    }
+
+    /// Caps the wall-clock time spent rendering.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        return self;
+    }
+
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        return self;
+    }
+
+    /// Restricts the context keys a template may look up. Keys are the full path of a name
+    /// through the sections it is nested in, so `{{#user}}{{name}}{{/user}}` looks up
+    /// `user.name`. Allowing a key also allows every name beneath it, so `user` allows
+    /// `user.name`, and sections leading to an allowed key may be entered.
+    pub fn allowed_keys<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_keys = Some(keys.into_iter().map(|key| key.into()).collect());
+        return self;
+    }
+
+    /// Restricts the partials and parents a template may include.
+    pub fn allowed_partials<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_partials = Some(names.into_iter().map(|name| name.into()).collect());
+        return self;
+    }
+
+    /// Restricts the pragmas a template may declare, so templates cannot turn off escaping or
+    /// strictness. An empty list denies every pragma.
+    pub fn allowed_pragmas<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_pragmas = Some(names.into_iter().map(|name| name.into()).collect());
+        return self;
+    }
+
+    pub(crate) fn allows_key(&self, identifier: &str) -> bool {
+        let Some(allowed_keys) = &self.allowed_keys else {
+            return true;
+        };
+
+        let mut prefix = String::new();
+        for part in identifier.split('.') {
+            if !prefix.is_empty() {
+                prefix.push('.');
+            }
+            prefix.push_str(part);
+            if allowed_keys.contains(&prefix) {
+                return true;
+            }
+        }
+
+        return false;
+    }
+
+    /// A section may also be entered to reach an allowed key nested beneath it.
+    pub(crate) fn allows_section(&self, path: &str) -> bool {
+        let Some(allowed_keys) = &self.allowed_keys else {
+            return true;
+        };
+
+        let prefix = [path, "."].concat();
+        return self.allows_key(path) || allowed_keys.iter().any(|key| key.starts_with(&prefix));
+    }
+
+    pub(crate) fn allows_partial(&self, name: &str) -> bool {
+        return match &self.allowed_partials {
+            Some(allowed_partials) => allowed_partials.contains(name),
+            None => true,
+        };
+    }
+
+    pub(crate) fn allows_pragma(&self, name: &str) -> bool {
+        return match &self.allowed_pragmas {
+            Some(allowed_pragmas) => allowed_pragmas.contains(name),
+            None => true,
+        };
+    }
+}
+
+/// Maps a dynamic name to the template to include.
+pub type NameResolver = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Controls which templates `{{>*name}}` and `{{<*name}}` may resolve to. Dynamic names come
+/// from the context, so without a policy user-controlled data can include any template.
+#[derive(Clone, Default)]
+pub enum DynamicNamePolicy {
+    /// Any loaded template may be included.
+    #[default]
+    Any,
+    /// Only the listed template names may be included.
+    AllowList(HashSet<String>),
+    /// Only template names starting with the prefix may be included.
+    Prefix(String),
+    /// Names are resolved beneath a directory, so `card` with the namespace `components`
+    /// includes `components/card`.
+    Namespace(String),
+    /// Maps a name from the context to the template to include, or `None` to reject it.
+    Resolver(NameResolver),
+}
+
+impl DynamicNamePolicy {
+    pub fn allow_list<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        return Self::AllowList(names.into_iter().map(|name| name.into()).collect());
+    }
+
+    pub fn resolver(resolver: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Self {
+        return Self::Resolver(Arc::new(resolver));
+    }
+
+    /// The template name to include for a dynamic name, or `None` if it is rejected.
+    pub fn resolve(&self, name: &str) -> Option<String> {
+        return match self {
+            DynamicNamePolicy::Any => Some(name.into()),
+            DynamicNamePolicy::AllowList(names) => names.get(name).cloned(),
+            DynamicNamePolicy::Prefix(prefix) => {
+                name.starts_with(prefix.as_str()).then(|| name.into())
+            }
+            DynamicNamePolicy::Namespace(namespace) => (!name.split('/').any(|part| part == ".."))
+                .then(|| [namespace.trim_end_matches('/'), "/", name].concat()),
+            DynamicNamePolicy::Resolver(resolver) => resolver(name),
+        };
+    }
+}
+
+impl std::fmt::Debug for DynamicNamePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            DynamicNamePolicy::Any => write!(f, "Any"),
+            DynamicNamePolicy::AllowList(names) => f.debug_tuple("AllowList").field(names).finish(),
+            DynamicNamePolicy::Prefix(prefix) => f.debug_tuple("Prefix").field(prefix).finish(),
+            DynamicNamePolicy::Namespace(namespace) => {
+                f.debug_tuple("Namespace").field(namespace).finish()
+            }
+            DynamicNamePolicy::Resolver(_) => write!(f, "Resolver(..)"),
+        };
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs;
+
+use rustache::{node::Escaper, EmptyContext, Rustache};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Context {
+    name: String,
+}
+
+#[test]
+fn builder_filters_files_and_configures_rendering() {
+    let directory = [env!("CARGO_TARGET_TMPDIR"), "/builder"].concat();
+    let directory = directory.as_str();
+    let _ = fs::remove_dir_all(directory);
+    fs::create_dir_all([directory, "/drafts"].concat()).unwrap();
+    fs::write([directory, "/page.html"].concat(), "<%name%> [<%missing%>]").unwrap();
+    fs::write([directory, "/notes.txt"].concat(), "notes").unwrap();
+    fs::write([directory, "/drafts/page.html"].concat(), "draft").unwrap();
+
+    let rustache = Rustache::builder(directory)
+        .glob("*.html")
+        .glob("**/*.html")
+        .exclude("drafts/**")
+        .extension("html")
+        .threads(1)
+        .delimiters("<%", "%>")
+        .escaper(Escaper::custom(|value| value.to_uppercase()))
+        .strict(false)
+        .build()
+        .expect("failed to load templates");
+
+    let mut names = rustache.partials.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["page"]);
+
+    let mut output = Vec::new();
+    rustache
+        .render(
+            "page",
+            &mut output,
+            &Context {
+                name: "world".into(),
+            },
+        )
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "WORLD []");
+}
+
+#[test]
+fn builder_can_use_the_global_pool() {
+    let rustache = Rustache::builder("views")
+        .glob("**/*.mustache")
+        .global_pool()
+        .build()
+        .expect("failed to load templates");
+
+    let mut output = Vec::new();
+    rustache
+        .render("partials/header", &mut output, &EmptyContext)
+        .expect("failed to render template");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "<header>Header</header>"
+    );
+}
+
+#[test]
+fn builder_requires_a_glob_or_an_extension() {
+    let directory = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("builder-patterns");
+    fs::create_dir_all(directory.join("partials")).unwrap();
+    fs::write(directory.join("partials/page.mustache"), "page").unwrap();
+    fs::write(directory.join("favicon.ico"), b"\x00\x00\x01\x00\xff").unwrap();
+    let directory = directory.to_str().unwrap();
+
+    let error = Rustache::builder(directory).build().expect_err("loaded without patterns");
+    assert!(error.to_string().contains("no glob patterns or extensions"));
+
+    let rustache = Rustache::builder(directory)
+        .extension("mustache")
+        .build()
+        .expect("failed to load templates");
+    let names = rustache.partials.keys().cloned().collect::<Vec<_>>();
+    assert_eq!(names, ["partials/page"]);
+}
@@ -0,0 +1,44 @@
+use rustache::{
+    check::{check, check_with_syntax},
+    lexer::{Delimiters, Syntax},
+};
+
+#[test]
+fn views_have_no_errors() {
+    let report = check("views", "**/*.mustache").expect("failed to check views");
+    assert!(!report.has_errors(), "{:#?}", report);
+}
+
+#[test]
+fn reports_broken_references() {
+    let directory = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("check");
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("page.mustache"), "{{>missing}}").unwrap();
+
+    let report = check(directory.to_str().unwrap(), "*.mustache").expect("failed to check views");
+    let errors = report.errors().collect::<Vec<_>>();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "partial: 'missing' does not exist");
+}
+
+#[test]
+fn checks_templates_with_the_syntax_they_are_rendered_with() {
+    let directory = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("check-syntax");
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("a.mustache"), "{{> b title=x}}").unwrap();
+    std::fs::write(directory.join("b.mustache"), "{{title}}").unwrap();
+    let directory = directory.to_str().unwrap();
+
+    let report = check(directory, "*.mustache").expect("failed to check views");
+    let errors = report.errors().collect::<Vec<_>>();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "1:7 syntax error: unexpected character: 't'");
+
+    let syntax = Syntax { partial_arguments: true, ..Default::default() };
+    let report = check_with_syntax(directory, "*.mustache", &syntax).expect("failed to check views");
+    assert!(!report.has_errors(), "{:#?}", report);
+
+    let syntax = Syntax { delimiters: Delimiters::new("<% ", "%>"), ..Default::default() };
+    assert!(check_with_syntax(directory, "*.mustache", &syntax).is_err());
+}
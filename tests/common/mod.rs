@@ -0,0 +1,6 @@
+use rustache::{loader::MemoryLoader, Rustache};
+
+pub fn rustache(templates: &[(&str, &str)]) -> Rustache {
+    return Rustache::from_loader(MemoryLoader::new(templates.iter().copied()))
+        .expect("failed to parse templates");
+}
@@ -0,0 +1,73 @@
+use std::{collections::HashMap, sync::Arc, thread};
+
+use rustache::{loader::FileSystemLoader, Rustache};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Context {
+    greeting: String,
+    fruit: Vec<String>,
+    name: String,
+    activity: String,
+    address: String,
+    smell: String,
+    parent: String,
+}
+
+fn context() -> Context {
+    return Context {
+        greeting: "world".into(),
+        fruit: vec!["apple".into(), "pear".into()],
+        name: "Lily".into(),
+        activity: "run".into(),
+        address: "1 Main Street".into(),
+        smell: "flowers".into(),
+        parent: "layouts/base".into(),
+    };
+}
+
+fn render_all(rustache: &Rustache) -> HashMap<String, String> {
+    let context = context();
+    return rustache
+        .partials
+        .keys()
+        .map(|name| {
+            let mut output = Vec::new();
+            rustache
+                .render(name, &mut output, &context)
+                .expect("failed to render template");
+            (name.clone(), String::from_utf8(output).unwrap())
+        })
+        .collect();
+}
+
+fn stress(rustache: Arc<Rustache>, expected: Arc<HashMap<String, String>>) {
+    let handles = (0..8)
+        .map(|_| {
+            let rustache = rustache.clone();
+            let expected = expected.clone();
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    assert_eq!(render_all(&rustache), *expected);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().expect("render thread panicked");
+    }
+}
+
+#[test]
+fn renders_views_concurrently() {
+    let rustache = Rustache::new("views", "**/*.mustache").expect("failed to parse templates");
+    let expected = Arc::new(render_all(&rustache));
+    assert_eq!(expected.len(), 7);
+
+    stress(Arc::new(rustache), expected.clone());
+
+    // Lazily loaded templates are parsed by whichever thread renders them first
+    let lazy = Rustache::lazy(FileSystemLoader::new("views", "**/*.mustache").unwrap());
+    stress(Arc::new(lazy), expected);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use rustache::{dependencies::Reference, Rustache};
+
+#[test]
+fn dependencies_of_template() {
+    let rustache = Rustache::new("views", "**/*.mustache").expect("failed to parse templates");
+    let dependencies = rustache.dependencies("index").expect("missing template");
+
+    assert!(dependencies.parents.contains(&Reference {
+        name: "layouts/base".into(),
+        dynamic: false
+    }));
+    assert!(dependencies.blocks.contains("head"));
+    assert!(dependencies.blocks.contains("body"));
+    assert!(dependencies.variables.contains(&Reference {
+        name: "greeting".into(),
+        dynamic: false
+    }));
+
+    let dependencies = rustache.dependencies("test").expect("missing template");
+
+    assert!(dependencies.parents.contains(&Reference {
+        name: "parent".into(),
+        dynamic: true
+    }));
+}
+
+#[test]
+fn dependents_of_layout() {
+    let rustache = Rustache::new("views", "**/*.mustache").expect("failed to parse templates");
+    let graph = rustache.dependency_graph();
+
+    let dependents = graph.dependents("partials/header");
+
+    assert!(dependents.contains("layouts/base"));
+    assert!(dependents.contains("index"));
+    assert!(dependents.contains("fruit"));
+    assert!(!dependents.contains("lily"));
+    assert!(graph.cycles().is_empty());
+}
+
+#[test]
+fn detects_include_cycles() {
+    let rustache = common::rustache(&[("a", "{{>b}}"), ("b", "{{<a}}{{/a}}")]);
+
+    let cycles = rustache.dependency_graph().cycles();
+
+    assert_eq!(cycles, vec![vec!["a", "b", "a"]]);
+}
+
+#[test]
+fn dynamic_variables_and_sections_are_marked_dynamic() {
+    let rustache = common::rustache(&[("page", "{{*field}}{{#*list}}{{/*list}}{{#items}}{{/items}}{{>*card}}")]);
+    let dependencies = rustache.dependencies("page").expect("missing template");
+
+    let reference = |name: &str, dynamic| Reference {
+        name: name.into(),
+        dynamic,
+    };
+    assert!(dependencies.variables.contains(&reference("field", true)));
+    assert!(dependencies.variables.contains(&reference("card", false)));
+    assert!(dependencies.sections.contains(&reference("list", true)));
+    assert!(dependencies.sections.contains(&reference("items", false)));
+}
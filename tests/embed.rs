@@ -0,0 +1,31 @@
+use rustache::{embed::generate, EmptyContext, Rustache};
+
+#[test]
+fn generates_embedded_views() {
+    let out_file = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("views.rs");
+
+    generate("views", "**/*.mustache", &out_file).expect("failed to embed views");
+
+    let source = std::fs::read_to_string(out_file).unwrap();
+    let rustache = Rustache::new("views", "**/*.mustache").expect("failed to parse templates");
+
+    for name in rustache.partials.keys() {
+        assert!(source.contains(&format!("({:?}, include_str!(", name)));
+    }
+}
+
+#[test]
+fn renders_embedded_views() {
+    static VIEWS: &[(&str, &str)] = &[
+        ("page", "{{>partials/footer}}"),
+        ("partials/footer", include_str!("../views/partials/footer.mustache")),
+    ];
+
+    let rustache = Rustache::embedded(VIEWS).expect("failed to parse templates");
+    let mut output = Vec::new();
+    rustache
+        .render("page", &mut output, &EmptyContext)
+        .expect("failed to render template");
+
+    assert_eq!(String::from_utf8(output).unwrap(), "<footer>Footer</footer>");
+}
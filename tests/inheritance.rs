@@ -0,0 +1,221 @@
+mod common;
+
+use serde_json::{json, Value};
+
+/// Renders `template` alongside `partials` and compares the output, mirroring the cases in
+/// the `~inheritance` spec.
+fn assert_renders(template: &str, partials: &[(&str, &str)], data: Value, expected: &str) {
+    let mut templates = partials.to_vec();
+    templates.push(("template", template));
+
+    let rustache = common::rustache(&templates);
+    let mut output = Vec::new();
+
+    rustache
+        .render("template", &mut output, &data)
+        .expect("failed to render template");
+
+    assert_eq!(String::from_utf8(output).unwrap(), expected, "{}", template);
+}
+
+#[test]
+fn blocks_without_parents() {
+    assert_renders(
+        "{{$title}}Default title{{/title}}",
+        &[],
+        json!({}),
+        "Default title",
+    );
+    assert_renders(
+        "{{$foo}}{{bar}}{{/foo}}",
+        &[],
+        json!({ "bar": "baz" }),
+        "baz",
+    );
+    assert_renders(
+        "{{$foo}}{{{bar}}}{{/foo}}",
+        &[],
+        json!({ "bar": "<baz>" }),
+        "<baz>",
+    );
+    assert_renders(
+        "{{$foo}}{{#bar}}{{baz}}{{/bar}}{{/foo}}",
+        &[],
+        json!({ "bar": { "baz": "qux" } }),
+        "qux",
+    );
+    assert_renders(
+        "{{$foo}}{{{bar}}}{{/foo}}",
+        &[],
+        json!({ "bar": "{{qux}}" }),
+        "{{qux}}",
+    );
+}
+
+#[test]
+fn parents_and_overrides() {
+    let include = [("include", "{{$foo}}default content{{/foo}}")];
+    assert_renders(
+        "{{<include}}{{/include}}",
+        &include,
+        json!({}),
+        "default content",
+    );
+
+    assert_renders(
+        "{{<super}}{{$title}}sub template title{{/title}}{{/super}}",
+        &[("super", "...{{$title}}Default title{{/title}}...")],
+        json!({}),
+        "...sub template title...",
+    );
+
+    let var = [("include", "{{$var}}var in include{{/var}}")];
+    assert_renders(
+        "{{<include}}{{$var}}var in template{{/var}}{{/include}}",
+        &var,
+        json!({ "var": "var in data" }),
+        "var in template",
+    );
+    assert_renders(
+        "{{<include}}{{/include}}",
+        &var,
+        json!({ "var": "var in data" }),
+        "var in include",
+    );
+
+    let parent = [(
+        "parent",
+        "|{{$stuff}}...{{/stuff}}{{$default}} default{{/default}}|",
+    )];
+    assert_renders(
+        "test {{<parent}}{{$stuff}}override{{/stuff}}{{/parent}}",
+        &parent,
+        json!({}),
+        "test |override default|",
+    );
+    assert_renders(
+        "test {{<parent}}{{$stuff}}override1{{/stuff}}{{/parent}} {{<parent}}{{$stuff}}override2{{/stuff}}{{/parent}}",
+        &parent,
+        json!({}),
+        "test |override1 default| |override2 default|",
+    );
+
+    assert_renders(
+        "{{<parent}}{{$stuff2}}override two{{/stuff2}}{{/parent}}",
+        &[(
+            "parent",
+            "|{{$stuff}}new default one{{/stuff}}, {{$stuff2}}new default two{{/stuff2}}|",
+        )],
+        json!({}),
+        "|new default one, override two|",
+    );
+
+    assert_renders(
+        "{{>include}}|{{<include}}{{/include}}",
+        &include,
+        json!({}),
+        "default content|default content",
+    );
+
+    assert_renders(
+        "{{<include}} asdfasd {{$foo}}hmm{{/foo}} asdfasdfasdf {{/include}}",
+        &include,
+        json!({}),
+        "hmm",
+    );
+}
+
+#[test]
+fn multi_level_inheritance() {
+    let levels = [
+        ("parent", "{{<older}}{{$a}}p{{/a}}{{/older}}"),
+        ("older", "{{<grandParent}}{{$a}}o{{/a}}{{/grandParent}}"),
+        ("grandParent", "{{$a}}g{{/a}}"),
+    ];
+    assert_renders(
+        "{{<parent}}{{$a}}c{{/a}}{{/parent}}",
+        &levels,
+        json!({}),
+        "c",
+    );
+    assert_renders("{{<parent}}{{/parent}}", &levels, json!({}), "p");
+
+    assert_renders(
+        "{{<parent}}{{$foo}}override{{/foo}}{{/parent}}",
+        &[
+            (
+                "parent",
+                "{{$foo}}default content{{/foo}} {{$bar}}{{<parent2}}{{/parent2}}{{/bar}}",
+            ),
+            (
+                "parent2",
+                "{{$foo}}parent2 default content{{/foo}} {{<parent}}{{$bar}}don't recurse{{/bar}}{{/parent}}",
+            ),
+        ],
+        json!({}),
+        "override override override don't recurse",
+    );
+}
+
+#[test]
+fn block_scope() {
+    assert_renders(
+        "{{<parent}}{{$block}}I say {{fruit}}.{{/block}}{{/parent}}",
+        &[(
+            "parent",
+            "{{#nested}}{{$block}}You say {{fruit}}.{{/block}}{{/nested}}",
+        )],
+        json!({ "fruit": "apples", "nested": { "fruit": "bananas" } }),
+        "I say bananas.",
+    );
+
+    // A block override does not replace a partial of the same name
+    assert_renders(
+        "{{<parent}}{{$header}}Page header{{/header}}{{/parent}}",
+        &[
+            ("parent", "{{>header}}|{{$header}}Default{{/header}}"),
+            ("header", "Partial header"),
+        ],
+        json!({}),
+        "Partial header|Page header",
+    );
+
+    // Nor does it reach into partials included by the parent
+    assert_renders(
+        "{{<parent}}{{$title}}Page{{/title}}{{/parent}}",
+        &[
+            ("parent", "{{$title}}Parent{{/title}}|{{>nested}}"),
+            ("nested", "{{$title}}Nested{{/title}}"),
+        ],
+        json!({}),
+        "Page|Nested",
+    );
+}
+
+#[test]
+fn super_renders_the_overridden_content() {
+    let base = ("base", "<head>{{$head}}<base>{{/head}}</head>");
+    assert_renders(
+        "{{<base}}{{$head}}{{@super}}<mine>{{/head}}{{/base}}",
+        &[base],
+        json!({}),
+        "<head><base><mine></head>",
+    );
+
+    // Each level of a parent chain can build on the one below it
+    assert_renders(
+        "{{<layout}}{{$head}}{{@super}}<page>{{/head}}{{/layout}}",
+        &[
+            base,
+            (
+                "layout",
+                "{{<base}}{{$head}}{{@super}}<layout>{{/head}}{{/base}}",
+            ),
+        ],
+        json!({}),
+        "<head><base><layout><page></head>",
+    );
+
+    // Outside of an override there is nothing to render
+    assert_renders("{{$head}}a{{@super}}b{{/head}}", &[], json!({}), "ab");
+}
@@ -0,0 +1,105 @@
+use rustache::{
+    loader::{FileSystemLoader, LayeredLoader, MemoryLoader},
+    node::RenderError,
+    EmptyContext, Rustache,
+};
+
+fn render(rustache: &Rustache, name: &str) -> Result<String, RenderError> {
+    let mut output = Vec::new();
+    return rustache
+        .render(name, &mut output, &EmptyContext)
+        .map(|_| String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn lazy_loading_parses_on_first_use() {
+    let rustache = Rustache::lazy(MemoryLoader::new([
+        ("page", "{{>footer}}"),
+        ("footer", "footer"),
+        ("broken", "{{#unclosed}}"),
+    ]));
+
+    assert!(rustache.partials.values().all(|template| !template.is_loaded()));
+    assert_eq!(render(&rustache, "page").unwrap(), "footer");
+    assert!(rustache.partials["page"].is_loaded());
+    assert!(rustache.partials["footer"].is_loaded());
+    assert!(!rustache.partials["broken"].is_loaded());
+
+    assert!(matches!(
+        render(&rustache, "broken"),
+        Err(RenderError::TemplateLoadFailed(_))
+    ));
+}
+
+#[test]
+fn layered_loader_prefers_earlier_layers() {
+    let loader = LayeredLoader::new()
+        .layer(MemoryLoader::new([("partials/header", "<header>Override</header>")]))
+        .layer(FileSystemLoader::new("views", "**/*.mustache").unwrap());
+
+    let rustache = Rustache::from_loader(loader).expect("failed to parse templates");
+
+    assert_eq!(
+        render(&rustache, "partials/header").unwrap(),
+        "<header>Override</header>"
+    );
+    assert_eq!(
+        render(&rustache, "partials/footer").unwrap(),
+        "<footer>Footer</footer>"
+    );
+}
+
+#[test]
+fn layered_directories_report_the_layer_of_each_template() {
+    let directory = [env!("CARGO_TARGET_TMPDIR"), "/layered"].concat();
+    let directory = directory.as_str();
+    let _ = std::fs::remove_dir_all(directory);
+    std::fs::create_dir_all([directory, "/partials"].concat()).unwrap();
+    std::fs::write(
+        [directory, "/partials/footer.mustache"].concat(),
+        "<footer>Theme</footer>",
+    )
+    .unwrap();
+
+    let rustache =
+        Rustache::layered(&[directory, "views"], "**/*.mustache").expect("failed to load");
+
+    assert_eq!(
+        render(&rustache, "partials/footer").unwrap(),
+        "<footer>Theme</footer>"
+    );
+    assert_eq!(rustache.layer("partials/footer"), Some(0));
+    assert_eq!(rustache.layer("partials/header"), Some(1));
+    assert_eq!(
+        rustache.origin("partials/footer"),
+        Some([directory, "/partials/footer.mustache"].concat())
+    );
+    assert_eq!(rustache.layer("missing"), None);
+}
+
+#[test]
+fn multi_byte_characters_load_across_read_boundaries() {
+    let directory = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("unicode");
+    std::fs::create_dir_all(&directory).unwrap();
+    let source = ["a".repeat(127), "é {{ café }}".into()].concat();
+    std::fs::write(directory.join("page.mustache"), &source).unwrap();
+
+    let rustache = Rustache::new(directory.to_str().unwrap(), "*.mustache").expect("failed to load");
+    let mut output = Vec::new();
+    rustache
+        .render("page", &mut output, &serde_json::json!({ "café": "crème" }))
+        .expect("failed to render template");
+
+    assert_eq!(String::from_utf8(output).unwrap(), ["a".repeat(127), "é crème".into()].concat());
+}
+
+#[test]
+fn invalid_utf8_is_a_load_error() {
+    let directory = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("invalid-utf8");
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("page.mustache"), b"line\n{{name}} \xff").unwrap();
+
+    let error = Rustache::new(directory.to_str().unwrap(), "*.mustache").expect_err("loaded invalid UTF-8");
+
+    assert!(format!("{:#}", error).contains("2:10 syntax error: invalid UTF-8"), "{:#}", error);
+}
@@ -0,0 +1,62 @@
+use std::fs;
+
+use rustache::{naming::Naming, Rustache};
+
+fn views(directory: &str, files: &[&str]) {
+    let _ = fs::remove_dir_all(directory);
+    for file in files {
+        let path = [directory, "/", file].concat();
+        fs::create_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+        fs::write(path, *file).unwrap();
+    }
+}
+
+#[test]
+fn strips_base_directory() {
+    let directory = [env!("CARGO_TARGET_TMPDIR"), "/naming/strip/views"].concat();
+    let directory = directory.as_str();
+    views(directory, &["index.mustache", "partials/header.mustache"]);
+
+    let rustache = Rustache::new(directory, "**/*.mustache").expect("failed to load templates");
+
+    let mut names = rustache.partials.keys().cloned().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, vec!["index", "partials/header"]);
+}
+
+#[test]
+fn reports_collisions() {
+    let directory = [env!("CARGO_TARGET_TMPDIR"), "/naming/collision"].concat();
+    let directory = directory.as_str();
+    views(directory, &["foo.mustache", "foo.html"]);
+
+    let error = Rustache::new(directory, "*").unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("template: 'foo' is provided by both"));
+
+    let naming = Naming::new().keep_extensions(true);
+    let rustache =
+        Rustache::with_naming(directory, "*", &naming).expect("failed to load templates");
+    assert!(rustache.partials.contains_key("foo.mustache"));
+    assert!(rustache.partials.contains_key("foo.html"));
+}
+
+#[test]
+fn aliases() {
+    let directory = [env!("CARGO_TARGET_TMPDIR"), "/naming/aliases"].concat();
+    let directory = directory.as_str();
+    views(directory, &["layouts/base.mustache"]);
+
+    let naming = Naming::new().alias("layout", "layouts/base");
+    let rustache =
+        Rustache::with_naming(directory, "**/*.mustache", &naming).expect("failed to load");
+    assert!(rustache.partials.contains_key("layout"));
+    assert!(rustache.partials.contains_key("layouts/base"));
+
+    let naming = Naming::new().alias("layout", "missing");
+    assert!(Rustache::with_naming(directory, "**/*.mustache", &naming).is_err());
+
+    let naming = Naming::new().alias("layouts/base", "layouts/base");
+    assert!(Rustache::with_naming(directory, "**/*.mustache", &naming).is_err());
+}
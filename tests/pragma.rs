@@ -0,0 +1,134 @@
+mod common;
+
+use rustache::{
+    loader::{parse_source, MemoryLoader},
+    node::{Escaper, RenderError},
+    pragma::{EscapeMode, Pragma},
+    sandbox::Sandbox,
+    Rustache,
+};
+
+fn render(rustache: &rustache::Rustache, name: &str, context: &serde_json::Value) -> String {
+    let mut output = Vec::new();
+    rustache
+        .render(name, &mut output, context)
+        .expect("failed to render template");
+    return String::from_utf8(output).unwrap();
+}
+
+#[test]
+fn pragmas_are_recorded_on_the_template() {
+    let rustache = common::rustache(&[(
+        "page",
+        "{{%IMPLICIT-ITERATOR iterator=item}}{{% ANCHORED-DOT }}{{%ESCAPE mode=\"none\"}}",
+    )]);
+
+    assert_eq!(
+        rustache.pragmas("page").unwrap(),
+        [
+            Pragma::ImplicitIterator("item".into()),
+            Pragma::AnchoredDot,
+            Pragma::Escape(EscapeMode::None),
+        ]
+    );
+}
+
+#[test]
+fn pragmas_apply_to_the_template_declaring_them() {
+    let rustache = common::rustache(&[
+        (
+            "list",
+            "{{%IMPLICIT-ITERATOR iterator=item}}{{%ESCAPE mode=none}}{{#items}}[{{item}}]{{/items}} {{> escaped}}",
+        ),
+        ("escaped", "{{html}}{{item}}"),
+        ("lenient", "{{%LENIENT}}[{{missing}}]{{> strict}}"),
+        ("strict", "{{missing}}"),
+    ]);
+    let context = serde_json::json!({
+        "items": ["<a>", "b"],
+        "html": "<b>",
+        "item": "!",
+    });
+
+    assert_eq!(render(&rustache, "list", &context), "[<a>][b] &lt;b&gt;!");
+
+    let error = rustache
+        .render("lenient", &mut Vec::new(), &context)
+        .unwrap_err();
+    assert!(matches!(error, RenderError::IdentifierDoesNotExist(name) if name == "missing"));
+}
+
+#[test]
+fn block_overrides_use_the_pragmas_of_their_template() {
+    let rustache = common::rustache(&[
+        ("base", "{{$body}}{{html}}{{/body}} {{html}}"),
+        (
+            "page",
+            "{{%ESCAPE mode=none}}{{<base}}{{$body}}{{html}}{{/body}}{{/base}}",
+        ),
+    ]);
+    let context = serde_json::json!({ "html": "<b>" });
+
+    assert_eq!(render(&rustache, "page", &context), "<b> &lt;b&gt;");
+}
+
+#[test]
+fn invalid_pragmas_are_syntax_errors() {
+    for (source, expected) in [
+        (
+            "{{%UNKNOWN}}",
+            "1:1 syntax error: unknown pragma: 'UNKNOWN'",
+        ),
+        (
+            "{{%ESCAPE}}",
+            "1:1 syntax error: pragma: 'ESCAPE' requires argument: 'mode'",
+        ),
+        (
+            "{{%ESCAPE mode=xml}}",
+            "1:1 syntax error: pragma: 'ESCAPE' argument: 'mode' cannot be: 'xml'",
+        ),
+        (
+            "{{%STRICT level=2}}",
+            "1:1 syntax error: pragma: 'STRICT' does not take argument: 'level'",
+        ),
+        (
+            "{{#items}}{{%LENIENT}}{{/items}}",
+            "1:11 syntax error: pragma: 'LENIENT' must be at the top level of the template",
+        ),
+    ] {
+        let error = parse_source(source).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+    }
+}
+
+#[test]
+fn escape_html_keeps_a_custom_escaper() {
+    let rustache = Rustache::builder("")
+        .escaper(Escaper::custom(|value| value.to_uppercase()))
+        .build_from_loader(MemoryLoader::new([("page", "{{%ESCAPE mode=html}}{{html}}")]))
+        .expect("failed to parse templates");
+    let context = serde_json::json!({ "html": "<b>" });
+
+    assert_eq!(render(&rustache, "page", &context), "<B>");
+}
+
+#[test]
+fn sandboxes_can_restrict_pragmas() {
+    let rustache = common::rustache(&[
+        ("unescaped", "{{%ESCAPE mode=none}}{{html}}"),
+        ("iterator", "{{%IMPLICIT-ITERATOR iterator=item}}{{#items}}{{item}}{{/items}}"),
+    ]);
+    let context = serde_json::json!({ "html": "<b>", "items": ["a", "b"] });
+    let sandbox = Sandbox::new().allowed_pragmas(["IMPLICIT-ITERATOR"]);
+
+    let error = rustache
+        .render_sandboxed("unescaped", &mut Vec::new(), &context, sandbox.clone())
+        .unwrap_err();
+    assert!(matches!(error, RenderError::PragmaNotAllowed(name) if name == "ESCAPE"));
+
+    let mut output = Vec::new();
+    rustache
+        .render_sandboxed("iterator", &mut output, &context, sandbox)
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "ab");
+}
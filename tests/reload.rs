@@ -0,0 +1,55 @@
+use std::{
+    fs,
+    time::{Duration, SystemTime},
+};
+
+use rustache::{reload::Reloading, EmptyContext};
+
+fn render(reloading: &Reloading, name: &str) -> String {
+    let mut output = Vec::new();
+    reloading
+        .render(name, &mut output, &EmptyContext)
+        .expect("failed to render template");
+    return String::from_utf8(output).unwrap();
+}
+
+/// Writes a template with a modification time `seconds` after the epoch, so changes are seen
+/// however coarse the filesystem timestamps are.
+fn write(path: &str, contents: &str, seconds: u64) {
+    fs::write(path, contents).unwrap();
+    let file = fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)).unwrap();
+}
+
+#[test]
+fn reloads_changed_templates() {
+    let directory = [env!("CARGO_TARGET_TMPDIR"), "/reload"].concat();
+    let directory = directory.as_str();
+    let _ = fs::remove_dir_all(directory);
+    fs::create_dir_all(directory).unwrap();
+    write(&[directory, "/page.mustache"].concat(), "first", 1);
+
+    let reloading = Reloading::new(directory, "*.mustache").expect("failed to load templates");
+    let snapshot = reloading.snapshot();
+    assert_eq!(render(&reloading, "page"), "first");
+
+    write(&[directory, "/page.mustache"].concat(), "second", 2);
+    write(&[directory, "/other.mustache"].concat(), "other", 2);
+
+    assert!(reloading.reload().is_empty());
+    assert_eq!(render(&reloading, "page"), "second");
+    assert_eq!(render(&reloading, "other"), "other");
+    assert_eq!(reloading.snapshot().origin("page"), Some([directory, "/page.mustache"].concat()));
+
+    // Snapshots taken before the reload are unaffected
+    assert!(snapshot.partials.contains_key("page"));
+    assert!(!snapshot.partials.contains_key("other"));
+
+    write(&[directory, "/page.mustache"].concat(), "{{#broken}}", 3);
+    fs::remove_file([directory, "/other.mustache"].concat()).unwrap();
+
+    let errors = reloading.reload();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(render(&reloading, "page"), "second");
+    assert!(!reloading.snapshot().partials.contains_key("other"));
+}
@@ -0,0 +1,722 @@
+mod common;
+
+use rustache::{lexer::Syntax, loader::MemoryLoader, node::RenderError, EmptyContext, Rustache};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Tree {
+    name: String,
+    children: Vec<Tree>,
+}
+
+fn tree(depth: usize) -> Tree {
+    return Tree {
+        name: depth.to_string(),
+        children: match depth {
+            0 => vec![],
+            _ => vec![tree(depth - 1)],
+        },
+    };
+}
+
+#[test]
+fn recursive_partials_render_within_depth_limit() {
+    let rustache = common::rustache(&[("tree", "{{name}}{{#children}},{{>tree}}{{/children}}")]);
+    let mut output = Vec::new();
+
+    rustache
+        .render("tree", &mut output, &tree(3))
+        .expect("failed to render template");
+
+    assert_eq!(String::from_utf8(output).unwrap(), "3,2,1,0");
+}
+
+#[test]
+fn recursive_partials_exceeding_depth_limit_fail() {
+    let mut rustache =
+        common::rustache(&[("tree", "{{name}}{{#children}},{{>tree}}{{/children}}")]);
+    rustache.max_depth = 3;
+
+    let result = rustache.render("tree", &mut Vec::new(), &tree(3));
+
+    assert!(matches!(
+        result,
+        Err(RenderError::DepthLimitExceeded { limit: 3, .. })
+    ));
+}
+
+#[test]
+fn partial_cycles_are_named() {
+    let rustache = common::rustache(&[("a", "{{>b}}"), ("b", "{{<a}}{{/a}}")]);
+
+    let result = rustache.render("a", &mut Vec::new(), &rustache::EmptyContext);
+
+    match result {
+        Err(error @ RenderError::DepthLimitExceeded { .. }) => {
+            assert_eq!(
+                error.to_string(),
+                "partial depth limit of 64 exceeded: a -> b -> a"
+            )
+        }
+        _ => panic!("expected depth limit error"),
+    }
+}
+
+#[test]
+fn relative_partials_resolve_against_the_including_template() {
+    #[derive(Serialize)]
+    struct Context {
+        widget: String,
+    }
+
+    let rustache = common::rustache(&[
+        ("pages/index", "{{<../layouts/base}}{{/../layouts/base}}"),
+        (
+            "layouts/base",
+            "{{>./header}}|{{>*widget}}|{{>../shared/footer}}",
+        ),
+        ("layouts/header", "header"),
+        ("layouts/card", "card"),
+        ("shared/footer", "footer"),
+        ("escape", "{{>../outside}}"),
+    ]);
+    let mut output = Vec::new();
+
+    rustache
+        .render(
+            "pages/index",
+            &mut output,
+            &Context {
+                widget: "./card".into(),
+            },
+        )
+        .expect("failed to render template");
+
+    assert_eq!(String::from_utf8(output).unwrap(), "header|card|footer");
+
+    let dependencies = rustache.dependencies("layouts/base").unwrap();
+    assert!(dependencies.templates().any(|name| name == "shared/footer"));
+
+    let result = rustache.render("escape", &mut Vec::new(), &Context { widget: "".into() });
+    assert!(matches!(result, Err(RenderError::PartialEscapesRoot(name)) if name == "../outside"));
+}
+
+#[test]
+fn relative_partials_in_block_overrides_resolve_against_the_overriding_template() {
+    let rustache = common::rustache(&[
+        ("pages/index", "{{<../layouts/base}}{{$body}}{{> ./card}}{{/body}}{{/../layouts/base}}"),
+        ("layouts/base", "<main>{{$body}}{{> ./card}}{{/body}}</main>"),
+        ("pages/card", "page card"),
+        ("layouts/card", "layout card"),
+    ]);
+    let mut output = Vec::new();
+
+    rustache
+        .render("pages/index", &mut output, &EmptyContext)
+        .expect("failed to render template");
+
+    assert_eq!(String::from_utf8(output).unwrap(), "<main>page card</main>");
+}
+
+#[test]
+fn block_overrides_are_scoped_to_their_parent() {
+    let rustache = common::rustache(&[
+        ("page", "{{<base}}{{$title}}Page{{/title}}{{/base}}|{{>title}}"),
+        ("base", "<{{$title}}Base{{/title}}>"),
+        ("title", "Default"),
+    ]);
+    let mut output = Vec::new();
+
+    rustache
+        .render("page", &mut output, &tree(0))
+        .expect("failed to render template");
+
+    assert_eq!(String::from_utf8(output).unwrap(), "<Page>|Default");
+}
+
+#[test]
+fn sections_on_objects_render_against_the_object() {
+    let rustache = common::rustache(&[(
+        "page",
+        "{{#user}}{{name}}{{/user}} {{#user.address}}{{city}}{{/user.address}} {{user.address.city}}",
+    )]);
+    let context = serde_json::json!({
+        "user": { "name": "ada", "address": { "city": "london" } },
+    });
+
+    let mut output = Vec::new();
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "ada london london");
+}
+
+#[test]
+fn render_block_renders_only_the_resolved_block() {
+    #[derive(Serialize)]
+    struct Context {
+        greeting: String,
+        fruit: Vec<String>,
+    }
+
+    let rustache = rustache::Rustache::new("views", "**/*.mustache").unwrap();
+    let context = Context {
+        greeting: "world".into(),
+        fruit: vec!["apple".into(), "pear".into()],
+    };
+
+    let mut output = Vec::new();
+    rustache
+        .render_block("index", "body", &mut output, &context)
+        .expect("failed to render block");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "<h1>Home</h1>\n  <p>Hello world</p>"
+    );
+
+    // Blocks that are not overridden render the parent's default content
+    let mut output = Vec::new();
+    rustache
+        .render_block("layouts/base", "head", &mut output, &context)
+        .expect("failed to render block");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "<title>No Title</title>"
+    );
+
+    let mut output = Vec::new();
+    rustache
+        .render_section("fruit", "fruit", &mut output, &context)
+        .expect("failed to render section");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\n    <li>apple</li>\n    <li>pear</li>"
+    );
+
+    let result = rustache.render_block("index", "missing", &mut Vec::new(), &context);
+    assert!(matches!(
+        result,
+        Err(RenderError::BlockDoesNotExist { template, block }) if template == "index" && block == "missing"
+    ));
+}
+
+#[test]
+fn render_block_does_not_render_the_rest_of_the_page() {
+    let rustache = common::rustache(&[
+        ("base", "<title>{{title}}</title>{{$body}}{{/body}}"),
+        ("page", "{{<base}}{{$body}}Hello {{name}}{{/body}}{{/base}}"),
+        ("twice", "{{$a}}A{{/a}}-{{$a}}A{{/a}}"),
+    ]);
+    let context = serde_json::json!({ "name": "ada" });
+
+    // `title` is only used outside the block, so it may be missing
+    let mut output = Vec::new();
+    rustache
+        .render_block("page", "body", &mut output, &context)
+        .expect("failed to render block");
+    assert_eq!(String::from_utf8(output).unwrap(), "Hello ada");
+
+    let mut output = Vec::new();
+    rustache
+        .render_block("twice", "a", &mut output, &context)
+        .expect("failed to render block");
+    assert_eq!(String::from_utf8(output).unwrap(), "A");
+}
+
+#[test]
+fn render_block_renders_blocks_inside_sections_with_their_context() {
+    let rustache = common::rustache(&[
+        ("p", "{{#user}}{{$side}}S {{name}}{{/side}}{{/user}}"),
+        ("list", "{{#users}}{{$row}}[{{name}}]{{/row}}{{/users}}"),
+    ]);
+    let context = serde_json::json!({
+        "user": { "name": "ada" },
+        "users": [{ "name": "ada" }, { "name": "grace" }],
+    });
+
+    let mut output = Vec::new();
+    rustache
+        .render_block("p", "side", &mut output, &context)
+        .expect("failed to render block");
+    assert_eq!(String::from_utf8(output).unwrap(), "S ada");
+
+    let mut output = Vec::new();
+    rustache
+        .render_block("list", "row", &mut output, &context)
+        .expect("failed to render block");
+    assert_eq!(String::from_utf8(output).unwrap(), "[ada][grace]");
+
+    let error = rustache
+        .render_block("list", "row", &mut Vec::new(), &serde_json::json!({ "users": [] }))
+        .unwrap_err();
+    assert!(matches!(error, RenderError::BlockDoesNotExist { .. }));
+}
+
+fn with_partial_arguments(templates: &[(&str, &str)]) -> Rustache {
+    return Rustache::builder("")
+        .partial_arguments(true)
+        .build_from_loader(MemoryLoader::new(templates.iter().copied()))
+        .expect("failed to parse templates");
+}
+
+#[test]
+fn partial_arguments_are_pushed_as_a_new_frame() {
+    let rustache = with_partial_arguments(&[
+        (
+            "page",
+            "{{#posts}}{{> card title=post.title href=post.url label=\"Read more\"}}{{/posts}}{{title}}",
+        ),
+        ("card", "<a href=\"{{href}}\">{{title}}</a> {{label}};"),
+    ]);
+    let context = serde_json::json!({
+        "title": "Posts",
+        "posts": [
+            { "post": { "title": "First", "url": "/first" } },
+            { "post": { "title": "Second", "url": "/second" } },
+        ],
+    });
+    let mut output = Vec::new();
+
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "<a href=\"/first\">First</a> Read more;<a href=\"/second\">Second</a> Read more;Posts"
+    );
+}
+
+#[test]
+fn invalid_partial_arguments_report_positions() {
+    for (source, position) in [
+        ("line\n  {{> card title}}", (2, 17)),
+        ("{{> card title=\"open}}", (1, 22)),
+        ("{{> card a=b a=c}}", (1, 1)),
+    ] {
+        let syntax = Syntax { partial_arguments: true, ..Default::default() };
+        let error = rustache::loader::parse_source_with_syntax(source, &syntax).unwrap_err();
+        assert_eq!(error.position(), Some(position), "{}", source);
+    }
+}
+
+#[test]
+fn partial_arguments_keep_the_context_of_the_caller() {
+    let rustache = with_partial_arguments(&[
+        ("page", "{{#names}}{{> card title=\"Name\"}}{{/names}}"),
+        ("card", "{{title}}: {{.}};"),
+    ]);
+    let context = serde_json::json!({ "names": ["Ada", "Grace"] });
+    let mut output = Vec::new();
+
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+
+    assert_eq!(String::from_utf8(output).unwrap(), "Name: Ada;Name: Grace;");
+}
+
+#[test]
+fn partial_arguments_are_off_by_default() {
+    let error = rustache::loader::parse_source("{{> card title=heading}}").unwrap_err();
+    assert_eq!(error.position(), Some((1, 10)));
+}
+
+#[test]
+fn inline_partial_definitions() {
+    let rustache = common::rustache(&[
+        (
+            "page",
+            "{{*define row}}<li>{{.}}</li>{{/row}}{{<base}}{{$body}}<ul>{{#items}}{{> row}}{{/items}}</ul>{{/body}}{{/base}}",
+        ),
+        ("base", "<main>{{$body}}{{/body}}</main>{{> row}}"),
+        ("row", "registry row"),
+    ]);
+    let context = serde_json::json!({ "items": ["a", "b"] });
+    let mut output = Vec::new();
+
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+
+    // Definitions are only visible within the template defining them
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "<main><ul><li>a</li><li>b</li></ul></main>registry row"
+    );
+
+    let dependencies = rustache.dependencies("page").unwrap();
+    assert!(dependencies.definitions.contains("row"));
+    assert_eq!(dependencies.templates().collect::<Vec<_>>(), ["base"]);
+
+    // Relative names in a definition resolve against the template defining it
+    let rustache = common::rustache(&[
+        ("pages/index", "{{*define row}}{{> ./card}}{{/row}}{{> row}}"),
+        ("pages/card", "page card"),
+        ("card", "root card"),
+    ]);
+    let mut output = Vec::new();
+    rustache
+        .render("pages/index", &mut output, &EmptyContext)
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "page card");
+
+    let error = rustache::loader::parse_source("{{*include row}}{{/row}}").unwrap_err();
+    assert!(error.to_string().contains("unknown directive: 'include'"));
+
+    let error = rustache::loader::parse_source("{{* }}").unwrap_err();
+    assert!(error.to_string().contains("expected identifier got: '}'"), "{}", error);
+
+    let error = rustache::loader::parse_source("{{#items}}{{*define row}}{{/row}}{{/items}}").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "1:11 syntax error: definition: 'row' must be at the top level of the template"
+    );
+}
+
+#[test]
+fn set_delimiter_accepts_any_non_whitespace_characters() {
+    let rustache = common::rustache(&[
+        ("pipes", "{{=| |=}}|name| |{html}| |&html|"),
+        (
+            "brackets",
+            "{{= [[ ]] =}}[[name]] [[{html}]] [[={{ }}=]]{{name}}",
+        ),
+        ("unicode", "{{=« »=}}«name» «{html}»"),
+    ]);
+    let context = serde_json::json!({ "name": "world", "html": "<b>" });
+
+    for (name, expected) in [
+        ("pipes", "world <b> <b>"),
+        ("brackets", "world <b> world"),
+        ("unicode", "world <b>"),
+    ] {
+        let mut output = Vec::new();
+        rustache
+            .render(name, &mut output, &context)
+            .expect("failed to render template");
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
+
+#[test]
+fn raw_variables_close_with_a_brace_before_custom_delimiters() {
+    let rustache = common::rustache(&[
+        ("percent", "{{=<% %>=}}<%{html}%> <%{ html }%> <%html%>"),
+        ("brace", "{{=<% }>=}}<%{html}}> <%html}>"),
+        ("single", "{{={ }=}}{{html}} {html}"),
+        ("triple", "{{={{{ }}}=}}{{{{html}}}} {{{html}}}"),
+    ]);
+    let context = serde_json::json!({ "html": "<b>" });
+
+    for (name, expected) in [
+        ("percent", "<b> <b> &lt;b&gt;"),
+        ("brace", "<b> &lt;b&gt;"),
+        ("single", "<b> &lt;b&gt;"),
+        ("triple", "<b> &lt;b&gt;"),
+    ] {
+        let mut output = Vec::new();
+        rustache
+            .render(name, &mut output, &context)
+            .expect("failed to render template");
+        assert_eq!(String::from_utf8(output).unwrap(), expected, "{}", name);
+    }
+}
+
+#[test]
+fn invalid_starting_delimiters_are_build_errors() {
+    for (open, close, message) in [
+        ("<% ", "%>", "delimiter: '<% ' cannot contain whitespace or '='"),
+        ("", "}}", "delimiters cannot be empty"),
+        ("<%", "=%>", "delimiter: '=%>' cannot contain whitespace or '='"),
+    ] {
+        let error = Rustache::builder("")
+            .delimiters(open, close)
+            .build_from_loader(MemoryLoader::new([("page", "<%name%>")]))
+            .expect_err("built with invalid delimiters");
+        assert_eq!(error.to_string(), ["invalid delimiters: ", message].concat());
+    }
+}
+
+#[test]
+fn empty_delimiters_are_syntax_errors() {
+    let error = rustache::loader::parse_source("{{=  =}}").unwrap_err();
+    assert!(error.to_string().contains("delimiters cannot be empty"));
+}
+
+#[test]
+fn starting_delimiters_apply_to_loaders() {
+    let loader = MemoryLoader::new([
+        (
+            "page",
+            "<div>{{ vue }}</div><%> header%><%name%> <%{html}%>",
+        ),
+        ("header", "<%=[ ]=%>[name]:"),
+    ]);
+    let rustache = Rustache::builder("")
+        .delimiters("<%", "%>")
+        .build_lazy(loader)
+        .expect("failed to build");
+    let context = serde_json::json!({ "name": "world", "html": "<b>" });
+
+    let mut output = Vec::new();
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "<div>{{ vue }}</div>world:world <b>"
+    );
+}
+
+#[test]
+fn verbatim_blocks_and_escaped_delimiters_render_as_text() {
+    let rustache = Rustache::builder("")
+        .escaped_delimiters(true)
+        .build_from_loader(MemoryLoader::new([
+            (
+                "page",
+                "{{name}} {{*verbatim}}<script>{{#items}}{{{.}}}{{/items}} {{> x}}</script>{{/verbatim}} \\{{name}}",
+            ),
+            ("custom", "{{=<% %>=}}<%*verbatim%>{{name}} <%name%><%/verbatim%> \\<%name%> {{name}}"),
+            ("backslash", "C:\\\\{{name}} C:\\name"),
+        ]))
+        .expect("failed to parse templates");
+    let context = serde_json::json!({ "name": "world" });
+
+    for (name, expected) in [
+        (
+            "page",
+            "world <script>{{#items}}{{{.}}}{{/items}} {{> x}}</script> {{name}}",
+        ),
+        ("custom", "{{name}} <%name%> <%name%> {{name}}"),
+        ("backslash", "C:\\world C:\\name"),
+    ] {
+        let mut output = Vec::new();
+        rustache
+            .render(name, &mut output, &context)
+            .expect("failed to render template");
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
+
+#[test]
+fn backslashes_before_tags_render_as_written_by_default() {
+    let rustache = common::rustache(&[("page", "C:\\{{dir}}")]);
+    let context = serde_json::json!({ "dir": "Users" });
+    let mut output = Vec::new();
+
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+
+    assert_eq!(String::from_utf8(output).unwrap(), "C:\\Users");
+}
+
+#[test]
+fn unclosed_verbatim_blocks_are_syntax_errors() {
+    let error = rustache::loader::parse_source("{{*verbatim}}{{name}}").unwrap_err();
+    assert!(error.to_string().contains("unclosed verbatim block"));
+}
+
+#[test]
+fn identifiers_accept_any_non_whitespace_characters() {
+    let rustache = common::rustache(&[(
+        "page",
+        "{{data-id}} {{ café }} {{#\n\tsection \n}}{{\tname\t}}{{/section}} {{{ data-id }}}",
+    )]);
+    let context = serde_json::json!({
+        "data-id": "<1>",
+        "café": "crème",
+        "section": { "name": "nested" },
+    });
+
+    let mut output = Vec::new();
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "&lt;1&gt; crème nested <1>"
+    );
+}
+
+#[test]
+fn invalid_characters_in_tags_report_positions() {
+    for (source, expected) in [
+        ("{{a b}}", "1:5 syntax error: unexpected character: 'b'"),
+        (
+            "text\n{{#}}",
+            "2:4 syntax error: expected identifier got: '}'",
+        ),
+        ("{{ café", "1:7 syntax error: unexpected end of file"),
+        ("{{> }}", "1:5 syntax error: expected identifier got: '}'"),
+    ] {
+        let error = rustache::loader::parse_source(source).unwrap_err();
+        assert_eq!(error.to_string(), expected);
+    }
+}
+
+#[test]
+fn dynamic_variables_and_sections_look_up_the_named_key() {
+    let rustache = common::rustache(&[
+        (
+            "table",
+            "{{#rows}}<tr>{{#columns}}<td>{{*field}}</td>{{/columns}}</tr>{{/rows}}",
+        ),
+        ("page", "{{* field }} {{{*field }}} {{&*field}} {{#*list}}[{{.}}]{{/*list}}{{^*empty}}none{{/empty}}"),
+        ("missing", "{{*other}}"),
+    ]);
+
+    let context = serde_json::json!({
+        "rows": [
+            { "columns": [{ "field": "name", "name": "Ada" }, { "field": "role", "role": "<admin>" }] },
+        ],
+    });
+    let mut output = Vec::new();
+    rustache
+        .render("table", &mut output, &context)
+        .expect("failed to render template");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "<tr><td>Ada</td><td>&lt;admin&gt;</td></tr>"
+    );
+
+    let context = serde_json::json!({
+        "field": "html",
+        "html": "<b>",
+        "list": "items",
+        "items": ["a", "b"],
+        "empty": "nothing",
+        "nothing": false,
+        "other": "absent",
+    });
+    let mut output = Vec::new();
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "&lt;b&gt; <b> <b> [a][b]none"
+    );
+
+    let error = rustache
+        .render("missing", &mut Vec::new(), &context)
+        .unwrap_err();
+    assert!(matches!(error, RenderError::IdentifierDoesNotExist(name) if name == "absent"));
+}
+
+#[test]
+fn dotted_names_index_into_lists() {
+    let rustache = common::rustache(&[
+        (
+            "index",
+            "{{items.0.name}} {{items.1.name}} {{#items.1}}{{name}}{{/items.1}} {{matrix.1.0}}",
+        ),
+        ("out_of_range", "{{items.2.name}}"),
+    ]);
+    let context = serde_json::json!({
+        "items": [{ "name": "first" }, { "name": "second" }],
+        "matrix": [["a", "b"], ["c", "d"]],
+    });
+
+    let mut output = Vec::new();
+    rustache
+        .render("index", &mut output, &context)
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "first second second c");
+
+    let error = rustache
+        .render("out_of_range", &mut Vec::new(), &context)
+        .unwrap_err();
+    assert!(matches!(error, RenderError::IdentifierDoesNotExist(name) if name == "items.2.name"));
+}
+
+#[test]
+fn length_property_is_opt_in() {
+    let loader = MemoryLoader::new([(
+        "page",
+        "{{items.length}} {{#items.length}}has items{{/items.length}} {{object.length}}",
+    )]);
+    let context = serde_json::json!({
+        "items": ["a", "b", "c"],
+        "object": { "length": "long" },
+    });
+
+    let rustache = Rustache::builder("")
+        .length_property(true)
+        .build_lazy(loader.clone())
+        .expect("failed to build");
+    let mut output = Vec::new();
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "3 has items long");
+
+    let empty = MemoryLoader::new([(
+        "page",
+        "{{items.length}} {{#items.length}}has{{/items.length}}{{^items.length}}none{{/items.length}}",
+    )]);
+    let rustache = Rustache::builder("")
+        .length_property(true)
+        .build_lazy(empty)
+        .expect("failed to build");
+    let mut output = Vec::new();
+    rustache
+        .render("page", &mut output, &serde_json::json!({ "items": [] }))
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "0 none");
+
+    let rustache = Rustache::lazy(loader);
+    let error = rustache
+        .render("page", &mut Vec::new(), &context)
+        .unwrap_err();
+    assert!(matches!(error, RenderError::IdentifierDoesNotExist(name) if name == "items.length"));
+}
+
+#[test]
+fn anchored_names_resolve_against_the_current_context() {
+    let rustache = common::rustache(&[(
+        "page",
+        "{{%ANCHORED-DOT}}{{.name}} {{#user}}{{.name}}{{/user}} {{#.user}}{{ .name }}{{/.user}} {{#list}}{{ . }}{{/list}}",
+    )]);
+    let context = serde_json::json!({
+        "name": "page",
+        "user": { "name": "ada" },
+        "list": ["x", "y"],
+    });
+
+    let mut output = Vec::new();
+    rustache
+        .render("page", &mut output, &context)
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "page ada ada xy");
+
+    let error = rustache::loader::parse_source("{{#.user}}{{/user}}").unwrap_err();
+    assert!(error.to_string().contains("unclosed section"));
+}
+
+#[test]
+fn anchored_names_skip_partial_arguments() {
+    let rustache = with_partial_arguments(&[
+        ("page", "{{> card title=\"arg\"}} {{> plain title=\"arg\"}}"),
+        ("card", "{{%ANCHORED-DOT}}[{{title}}] [{{.title}}]"),
+        ("plain", "[{{title}}] [{{.title}}]"),
+    ]);
+    let mut output = Vec::new();
+
+    rustache
+        .render("card", &mut output, &serde_json::json!({ "title": "context" }))
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "[context] [context]");
+
+    let error = rustache
+        .render("page", &mut Vec::new(), &serde_json::json!({}))
+        .unwrap_err();
+    assert!(matches!(error, RenderError::IdentifierDoesNotExist(name) if name == ".title"));
+
+    let error = rustache
+        .render("plain", &mut Vec::new(), &serde_json::json!({ "title": "context" }))
+        .unwrap_err();
+    assert!(matches!(error, RenderError::IdentifierDoesNotExist(name) if name == ".title"));
+}
@@ -0,0 +1,190 @@
+mod common;
+
+use std::time::Duration;
+
+use rustache::{
+    loader::MemoryLoader,
+    node::RenderError,
+    sandbox::{CancellationToken, DynamicNamePolicy, Sandbox},
+    Rustache,
+};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Context {
+    name: String,
+    secret: String,
+    items: Vec<u32>,
+    page: String,
+}
+
+fn context() -> Context {
+    return Context {
+        name: "world".into(),
+        secret: "hunter2".into(),
+        items: (0..1000).collect(),
+        page: "admin".into(),
+    };
+}
+
+#[test]
+fn output_limit() {
+    let rustache = common::rustache(&[("page", "{{#items}}{{.}}{{/items}}")]);
+
+    let result = rustache.render_sandboxed(
+        "page",
+        &mut Vec::new(),
+        &context(),
+        Sandbox::new().max_output_bytes(100),
+    );
+
+    assert!(matches!(result, Err(RenderError::OutputLimitExceeded(100))));
+}
+
+#[test]
+fn evaluation_limit() {
+    let rustache = common::rustache(&[("page", "{{#items}}<li>{{.}}</li>{{/items}}")]);
+
+    let result = rustache.render_sandboxed(
+        "page",
+        &mut Vec::new(),
+        &context(),
+        Sandbox::new().max_evaluations(500),
+    );
+
+    assert!(matches!(result, Err(RenderError::EvaluationLimitExceeded(500))));
+}
+
+#[test]
+fn depth_limit_cannot_be_raised_by_a_sandbox() {
+    let rustache = Rustache::builder("")
+        .max_depth(3)
+        .build_from_loader(MemoryLoader::new([("page", "{{#c}}{{> page}}{{/c}}")]))
+        .expect("failed to parse templates");
+    let context = serde_json::json!({ "c": true });
+
+    let result = rustache.render_sandboxed("page", &mut Vec::new(), &context, Sandbox::new().max_depth(20000));
+
+    assert!(matches!(result, Err(RenderError::DepthLimitExceeded { limit: 3, .. })));
+}
+
+#[test]
+fn timeout_and_cancellation() {
+    let rustache = common::rustache(&[("page", "{{#items}}{{name}}{{/items}}")]);
+
+    let result = rustache.render_sandboxed(
+        "page",
+        &mut Vec::new(),
+        &context(),
+        Sandbox::new().timeout(Duration::ZERO),
+    );
+    assert!(matches!(result, Err(RenderError::Timeout(_))));
+
+    let token = CancellationToken::new();
+    token.cancel();
+
+    let result = rustache.render_sandboxed(
+        "page",
+        &mut Vec::new(),
+        &context(),
+        Sandbox::new().cancellation(token),
+    );
+    assert!(matches!(result, Err(RenderError::Cancelled)));
+}
+
+#[test]
+fn allowed_keys_and_partials() {
+    let rustache = common::rustache(&[
+        ("page", "Hello {{name}}{{>footer}}"),
+        ("leak", "{{secret}}"),
+        ("dynamic", "{{>*page}}"),
+        ("footer", "!"),
+        ("admin", "admin only"),
+    ]);
+    let sandbox = Sandbox::new()
+        .allowed_keys(["name", "page"])
+        .allowed_partials(["footer"]);
+
+    let mut output = Vec::new();
+    rustache
+        .render_sandboxed("page", &mut output, &context(), sandbox.clone())
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "Hello world!");
+
+    let result = rustache.render_sandboxed("leak", &mut Vec::new(), &context(), sandbox.clone());
+    assert!(matches!(result, Err(RenderError::IdentifierNotAllowed(key)) if key == "secret"));
+
+    let result = rustache.render_sandboxed("dynamic", &mut Vec::new(), &context(), sandbox);
+    assert!(matches!(result, Err(RenderError::PartialNotAllowed(name)) if name == "admin"));
+}
+
+#[test]
+fn allowed_keys_apply_to_the_full_path_of_nested_names() {
+    let rustache = common::rustache(&[
+        ("user", "{{#user}}{{name}}{{/user}}"),
+        ("nested", "{{name}} {{#user}}{{name}}{{/user}}"),
+    ]);
+    let context = serde_json::json!({ "name": "page", "user": { "name": "ada" } });
+
+    let mut output = Vec::new();
+    rustache
+        .render_sandboxed("user", &mut output, &context, Sandbox::new().allowed_keys(["user"]))
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "ada");
+
+    let sandbox = Sandbox::new().allowed_keys(["name", "user"]);
+    let mut output = Vec::new();
+    rustache
+        .render_sandboxed("nested", &mut output, &context, sandbox)
+        .expect("failed to render template");
+    assert_eq!(String::from_utf8(output).unwrap(), "page ada");
+
+    // Allowing a top-level key does not allow the same key inside other sections
+    let sandbox = Sandbox::new().allowed_keys(["name", "user.id"]);
+    let result = rustache.render_sandboxed("nested", &mut Vec::new(), &context, sandbox);
+    assert!(matches!(result, Err(RenderError::IdentifierNotAllowed(key)) if key == "user.name"));
+}
+
+#[derive(Serialize)]
+struct Dynamic {
+    component: String,
+}
+
+#[test]
+fn dynamic_name_policies() {
+    let mut rustache = common::rustache(&[
+        ("page", "{{>*component}}"),
+        ("layout", "{{<*component}}{{/*component}}"),
+        ("components/card", "card"),
+        ("admin", "admin only"),
+    ]);
+    let render = |rustache: &rustache::Rustache, name: &str, component: &str| {
+        let mut output = Vec::new();
+        let context = Dynamic {
+            component: component.into(),
+        };
+        return rustache
+            .render(name, &mut output, &context)
+            .map(|_| String::from_utf8(output).unwrap());
+    };
+
+    rustache.dynamic_names = DynamicNamePolicy::allow_list(["components/card"]);
+    assert_eq!(render(&rustache, "page", "components/card").unwrap(), "card");
+    assert!(matches!(
+        render(&rustache, "layout", "admin"),
+        Err(RenderError::DynamicNameNotAllowed { name, .. }) if name == "admin"
+    ));
+
+    rustache.dynamic_names = DynamicNamePolicy::Prefix("components/".into());
+    assert_eq!(render(&rustache, "layout", "components/card").unwrap(), "card");
+    assert!(render(&rustache, "page", "admin").is_err());
+
+    rustache.dynamic_names = DynamicNamePolicy::Namespace("components".into());
+    assert_eq!(render(&rustache, "page", "card").unwrap(), "card");
+    assert!(render(&rustache, "page", "../admin").is_err());
+
+    rustache.dynamic_names =
+        DynamicNamePolicy::resolver(|name| (name == "card").then(|| "components/card".into()));
+    assert_eq!(render(&rustache, "page", "card").unwrap(), "card");
+    assert!(render(&rustache, "page", "admin").is_err());
+}